@@ -0,0 +1,174 @@
+//! Crate-local module for admonitions.
+//!
+//! An admonition is a div with one of the classes `note`, `tip`, `important`,
+//! `warning` or `caution`, e.g. `::: warning`. It is rendered as a colored box
+//! with a title, which defaults to the name of the kind and can be set with a
+//! `title` attribute. In HTML output, an admonition with the class
+//! `collapsible` can be folded and is closed initially.
+
+use jotdown::Attributes;
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use jotdown::{Container, Event};
+
+use crate::has_class;
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use crate::utils::escape_html;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl Kind {
+    const ALL: [Kind; 5] = [
+        Kind::Note,
+        Kind::Tip,
+        Kind::Important,
+        Kind::Warning,
+        Kind::Caution,
+    ];
+
+    /// Returns the kind of admonition of a div, if it is one.
+    pub(crate) fn of_div(class: &str, attrs: &Attributes) -> Option<Self> {
+        let classes = match attrs.get("class") {
+            Some(classes) => format!("{class} {classes}"),
+            None => class.to_string(),
+        };
+        Self::ALL
+            .into_iter()
+            .find(|kind| has_class(&classes, kind.name()))
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Kind::Note => "note",
+            Kind::Tip => "tip",
+            Kind::Important => "important",
+            Kind::Warning => "warning",
+            Kind::Caution => "caution",
+        }
+    }
+
+    fn default_title(self) -> &'static str {
+        match self {
+            Kind::Note => "Note",
+            Kind::Tip => "Tip",
+            Kind::Important => "Important",
+            Kind::Warning => "Warning",
+            Kind::Caution => "Caution",
+        }
+    }
+
+    /// Returns the title of an admonition with the given attributes.
+    pub(crate) fn title(self, attrs: &Attributes) -> String {
+        attrs
+            .get("title")
+            .map_or_else(|| self.default_title().to_string(), ToString::to_string)
+    }
+
+    /// Returns the `xcolor` color of the admonition in LaTeX output.
+    #[cfg(feature = "latex")]
+    pub(crate) fn color(self) -> &'static str {
+        match self {
+            Kind::Note => "blue",
+            Kind::Tip => "teal",
+            Kind::Important => "violet",
+            Kind::Warning => "orange",
+            Kind::Caution => "red",
+        }
+    }
+}
+
+/// Returns an iterator adapter that replaces admonitions by `<aside>` or,
+/// if collapsible, `<details>` elements.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+pub(crate) fn html<'s>(events: impl Iterator<Item = Event<'s>>) -> impl Iterator<Item = Event<'s>> {
+    // Whether each open div is an admonition, and if so, a collapsible one
+    let mut divs = Vec::new();
+    events.flat_map(move |event| match event {
+        Event::Start(Container::Div { class }, ref attrs) => {
+            let Some(kind) = Kind::of_div(class, attrs) else {
+                divs.push(None);
+                return vec![event];
+            };
+            let collapsible = attrs
+                .get("class")
+                .is_some_and(|classes| has_class(&classes.to_string(), "collapsible"));
+            divs.push(Some(collapsible));
+
+            let id = attrs
+                .get("id")
+                .map(|id| format!(r#" id="{}""#, escape_html(&id.to_string())))
+                .unwrap_or_default();
+            let title = escape_html(&kind.title(attrs));
+            let name = kind.name();
+            raw_block(match collapsible {
+                true => format!(
+                    "<details{id} class=\"admonition {name}\">\n\
+                     <summary class=\"admonition-title\">{title}</summary>"
+                ),
+                false => format!(
+                    "<aside{id} class=\"admonition {name}\" role=\"note\">\n\
+                     <p class=\"admonition-title\">{title}</p>"
+                ),
+            })
+        }
+        Event::End(Container::Div { .. }) => match divs.pop().flatten() {
+            Some(true) => raw_block("</details>".into()),
+            Some(false) => raw_block("</aside>".into()),
+            None => vec![event],
+        },
+        event => vec![event],
+    })
+}
+
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn raw_block<'s>(html: String) -> Vec<Event<'s>> {
+    vec![
+        Event::Start(Container::RawBlock { format: "html" }, Attributes::new()),
+        Event::Str(html.into()),
+        Event::End(Container::RawBlock { format: "html" }),
+    ]
+}
+
+#[cfg(all(test, any(feature = "html", feature = "html-wasm")))]
+mod tests {
+    use jotdown::{html::Renderer, Parser, Render};
+
+    use super::*;
+
+    fn render(src: &str) -> String {
+        let mut s = String::new();
+        Renderer::default()
+            .push(html(Parser::new(src)), &mut s)
+            .unwrap();
+        s
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            render("::: warning\nCareful.\n:::\n\n::: other\nText.\n:::"),
+            concat!(
+                "<aside class=\"admonition warning\" role=\"note\">\n",
+                "<p class=\"admonition-title\">Warning</p>\n",
+                "<p>Careful.</p>\n",
+                "</aside>\n",
+                "<div class=\"other\">\n<p>Text.</p>\n</div>\n",
+            )
+        );
+        assert_eq!(
+            render("{title=\"A & B\" .collapsible}\n::: tip\nText.\n:::"),
+            concat!(
+                "<details class=\"admonition tip\">\n",
+                "<summary class=\"admonition-title\">A &amp; B</summary>\n",
+                "<p>Text.</p>\n",
+                "</details>\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,465 @@
+//! Crate-local module for resolving the paths of assets, such as images.
+//!
+//! Assets are referenced relative to the source file of the text they appear
+//! in, so their paths are resolved against its directory. For PDF output, the
+//! assets are staged into the directory tectonic runs in under names of their
+//! own, since tectonic only reads files from there.
+//!
+//! LaTeX cannot include SVG images, so they are converted to PDF, which is
+//! cached in the build directory under the hash of the SVG file.
+//!
+//! In HTML output, images are either linked as written, embedded as data URIs
+//! or copied into an asset directory under the hash of their content (see
+//! [`AssetMode`]). Copied raster images can additionally get downscaled
+//! variants in a `srcset`.
+
+#[cfg(any(feature = "html", feature = "html-wasm", feature = "latex"))]
+use std::fs;
+#[cfg(any(feature = "pdf", feature = "html", feature = "html-wasm"))]
+use std::io;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use jotdown::{AttributeValue, Container, Event};
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use log::warn;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "latex")]
+use svg2pdf::usvg::{self, fontdb, PostProcessingSteps, TreeParsing, TreePostProc};
+
+#[cfg(feature = "latex")]
+use crate::latex::{LatexError, LatexErrorKind};
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use crate::AssetMode;
+use crate::ParsedDocument;
+
+/// The directory in the build directory that assets are staged into.
+#[cfg(feature = "pdf")]
+const STAGING_DIR: &str = "assets";
+
+/// The directory in the build directory that converted SVG images are cached
+/// in.
+#[cfg(feature = "latex")]
+const SVG_CACHE_DIR: &str = "svg";
+
+/// The widths of the downscaled variants of raster images in a `srcset`.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+const SRCSET_WIDTHS: [u32; 3] = [480, 960, 1440];
+
+/// Returns a hash of the content of a file, for naming it.
+fn fingerprint(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Returns whether `dest` refers to a local file, rather than e.g. a URL.
+pub(crate) fn is_local(dest: &str) -> bool {
+    !dest.is_empty()
+        && !dest.starts_with('#')
+        && !dest.starts_with("data:")
+        && !dest.contains("://")
+}
+
+/// An asset that is referenced in a document.
+pub(crate) struct Asset {
+    /// The path of the asset, resolved against the directory of the text.
+    pub(crate) path: PathBuf,
+    /// The source file of the first text referencing the asset.
+    #[cfg_attr(not(feature = "pdf"), allow(dead_code))]
+    pub(crate) text: Option<PathBuf>,
+    /// The path the asset is referenced by in the output.
+    output: String,
+    /// The `srcset` of the image in HTML output.
+    srcset: Option<String>,
+}
+
+/// The local assets of a document.
+pub(crate) struct Assets {
+    assets: Vec<Asset>,
+    /// The index of the asset each destination of each text refers to.
+    indices: HashMap<(usize, String), usize>,
+}
+
+impl Assets {
+    /// Collects the images of a document.
+    pub(crate) fn new(parsed: &ParsedDocument) -> Self {
+        let document = parsed.document();
+        let mut assets: Vec<Asset> = Vec::new();
+        let mut indices = HashMap::new();
+        for i in 0..parsed.len() {
+            let source = document.path(i);
+            let dir = source.and_then(Path::parent);
+            for event in parsed.events(i) {
+                let Event::Start(Container::Image(dest, _), _) = event else {
+                    continue;
+                };
+                if !is_local(&dest) || indices.contains_key(&(i, dest.to_string())) {
+                    continue;
+                }
+
+                let path = match dir {
+                    Some(dir) => dir.join(&*dest),
+                    None => PathBuf::from(&*dest),
+                };
+                let index = match assets.iter().position(|asset| asset.path == path) {
+                    Some(index) => index,
+                    None => {
+                        assets.push(Asset {
+                            output: path.to_string_lossy().into_owned(),
+                            path,
+                            text: source.map(Path::to_path_buf),
+                            srcset: None,
+                        });
+                        assets.len() - 1
+                    }
+                };
+                indices.insert((i, dest.to_string()), index);
+            }
+        }
+        Self { assets, indices }
+    }
+
+    #[cfg(feature = "pdf")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Returns the first asset that does not exist.
+    #[cfg(feature = "pdf")]
+    pub(crate) fn missing(&self) -> Option<&Asset> {
+        self.assets.iter().find(|asset| !asset.path.is_file())
+    }
+
+    /// Converts the SVG images to PDF, caching the converted files in
+    /// `build_dir`, and refers to the converted files from then on.
+    #[cfg(feature = "latex")]
+    pub(crate) fn convert_svgs(&mut self, build_dir: &Path) -> Result<(), LatexError> {
+        let cache_dir = build_dir.join(SVG_CACHE_DIR);
+        for asset in &mut self.assets {
+            let is_svg = asset
+                .path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+            // Missing files are reported when they are included
+            let Some(svg) = is_svg.then(|| fs::read(&asset.path).ok()).flatten() else {
+                continue;
+            };
+
+            let pdf = cache_dir.join(fingerprint(&svg)).with_extension("pdf");
+            if !pdf.is_file() {
+                let bytes = svg_to_pdf(&svg, asset.path.parent()).map_err(|e| LatexError {
+                    document_name: None,
+                    kind: LatexErrorKind::Svg {
+                        path: asset.path.clone(),
+                        source: e,
+                    },
+                })?;
+                fs::create_dir_all(&cache_dir)?;
+                fs::write(&pdf, bytes)?;
+            }
+            asset.output = pdf.to_string_lossy().into_owned();
+            asset.path = pdf;
+        }
+        Ok(())
+    }
+
+    /// Copies the assets into `root`, and refers to the copies from then on.
+    #[cfg(feature = "pdf")]
+    pub(crate) fn stage(&mut self, root: &Path) -> io::Result<()> {
+        if self.assets.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(root.join(STAGING_DIR))?;
+        for (i, asset) in self.assets.iter_mut().enumerate() {
+            // Plain names, as LaTeX chokes on spaces and special characters
+            let mut output = format!("{STAGING_DIR}/{i}");
+            if let Some(extension) = asset.path.extension() {
+                output = format!("{output}.{}", extension.to_string_lossy());
+            }
+            fs::copy(&asset.path, root.join(&output))?;
+            asset.output = output;
+        }
+        Ok(())
+    }
+
+    /// Prepares the assets for HTML output in the given mode. Copied assets
+    /// are written to `dir`, relative to `root`, the directory of the HTML
+    /// file, with downscaled variants if `srcset` is set.
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    pub(crate) fn html(
+        &mut self,
+        mode: AssetMode,
+        root: &Path,
+        dir: &Path,
+        srcset: bool,
+    ) -> io::Result<()> {
+        if mode == AssetMode::Link {
+            // The destinations are kept as written, relative to the output
+            self.indices.clear();
+            return Ok(());
+        }
+        for asset in &mut self.assets {
+            let Ok(bytes) = fs::read(&asset.path) else {
+                warn!("Image {:?} not found", asset.path);
+                continue;
+            };
+            if mode == AssetMode::Embed {
+                let mime = mime_type(&asset.path);
+                asset.output = format!("data:{mime};base64,{}", BASE64.encode(&bytes));
+                continue;
+            }
+
+            let stem = fingerprint(&bytes);
+            let name = match asset.path.extension() {
+                Some(extension) => format!("{stem}.{}", extension.to_string_lossy()),
+                None => stem.clone(),
+            };
+            let path = root.join(dir).join(&name);
+            if !path.is_file() {
+                fs::create_dir_all(root.join(dir))?;
+                fs::write(&path, &bytes)?;
+            }
+            asset.output = dir.join(&name).to_string_lossy().into_owned();
+            if srcset && is_raster(&asset.path) {
+                asset.srcset = downscale(&path, &stem, dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator adapter that replaces the destinations of the
+    /// images of the text at `index` by the paths of their assets.
+    pub(crate) fn rewrite<'a, 's, I>(
+        &'a self,
+        index: usize,
+        events: I,
+    ) -> impl Iterator<Item = Event<'s>> + 'a
+    where
+        I: Iterator<Item = Event<'s>> + 'a,
+        's: 'a,
+    {
+        events.map(move |event| match event {
+            Event::Start(Container::Image(dest, link_type), mut attrs) => {
+                let asset = self
+                    .indices
+                    .get(&(index, dest.to_string()))
+                    .map(|&i| &self.assets[i]);
+                if let Some(srcset) = asset.and_then(|asset| asset.srcset.clone()) {
+                    attrs.insert("srcset", AttributeValue::from(srcset));
+                }
+                Event::Start(Container::Image(self.output(index, dest), link_type), attrs)
+            }
+            Event::End(Container::Image(dest, link_type)) => {
+                Event::End(Container::Image(self.output(index, dest), link_type))
+            }
+            event => event,
+        })
+    }
+
+    fn output<'s>(&self, index: usize, dest: Cow<'s, str>) -> Cow<'s, str> {
+        match self.indices.get(&(index, dest.to_string())) {
+            Some(&i) => self.assets[i].output.clone().into(),
+            None => dest,
+        }
+    }
+}
+
+/// Returns the media type of an image, for data URIs.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Returns whether an image can be downscaled for a `srcset`.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn is_raster(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    matches!(extension.as_deref(), Some("png" | "jpg" | "jpeg"))
+}
+
+/// Writes the downscaled variants of the copied image at `path` next to it,
+/// and returns the `srcset` of the image, referring to the copies in `dir`
+/// as the HTML output does. Images that are too small to be
+/// downscaled or whose size cannot be read have no `srcset`.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn downscale(path: &Path, stem: &str, dir: &Path) -> io::Result<Option<String>> {
+    let (width, _) = match image::image_dimensions(path) {
+        Ok(dimensions) => dimensions,
+        Err(e) => {
+            warn!("Could not read image {path:?}: {e}");
+            return Ok(None);
+        }
+    };
+    let widths: Vec<_> = SRCSET_WIDTHS.into_iter().filter(|w| *w < width).collect();
+    if widths.is_empty() {
+        return Ok(None);
+    }
+
+    let mut image = None;
+    let mut candidates = Vec::new();
+    for w in widths {
+        let mut variant = path.with_file_name(format!("{stem}-{w}w"));
+        if let Some(extension) = path.extension() {
+            variant.set_extension(extension);
+        }
+        if !variant.is_file() {
+            if image.is_none() {
+                image = Some(image::open(path).map_err(io::Error::other)?);
+            }
+            image
+                .as_ref()
+                .unwrap()
+                .resize(w, u32::MAX, image::imageops::FilterType::Lanczos3)
+                .save(&variant)
+                .map_err(io::Error::other)?;
+        }
+        let src = dir.join(variant.file_name().unwrap());
+        candidates.push(format!("{} {w}w", src.to_string_lossy()));
+    }
+    let src = dir.join(path.file_name().unwrap());
+    candidates.push(format!("{} {width}w", src.to_string_lossy()));
+    Ok(Some(candidates.join(", ")))
+}
+
+/// Converts an SVG image to PDF, with its text converted to paths.
+#[cfg(feature = "latex")]
+fn svg_to_pdf(svg: &[u8], resources_dir: Option<&Path>) -> Result<Vec<u8>, usvg::Error> {
+    static FONTS: std::sync::OnceLock<fontdb::Database> = std::sync::OnceLock::new();
+
+    let options = usvg::Options {
+        resources_dir: resources_dir.map(Path::to_path_buf),
+        ..usvg::Options::default()
+    };
+    let mut tree = usvg::Tree::from_data(svg, &options)?;
+    let fonts = FONTS.get_or_init(|| {
+        let mut fonts = fontdb::Database::new();
+        fonts.load_system_fonts();
+        fonts
+    });
+    tree.postprocess(PostProcessingSteps::default(), fonts);
+    Ok(svg2pdf::convert_tree(&tree, svg2pdf::Options::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Document;
+
+    #[test]
+    fn test_resolve() {
+        let mut document = Document::default();
+        document.texts([
+            "![A](img/a.png) ![B](https://example.com/b.png)",
+            "![A](../src/img/a.png) ![C](c.png)",
+        ]);
+        document.paths = vec![
+            Some(PathBuf::from("src/one.dj")),
+            Some(PathBuf::from("src/two.dj")),
+        ];
+        let parsed = document.parse();
+        let assets = Assets::new(&parsed);
+        assert_eq!(assets.assets.len(), 3);
+
+        let dests: Vec<_> = assets
+            .rewrite(1, parsed.events(1))
+            .filter_map(|event| match event {
+                Event::Start(Container::Image(dest, _), _) => Some(dest.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(dests, ["src/../src/img/a.png", "src/c.png"]);
+    }
+
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    #[test]
+    fn test_html() {
+        let dir = std::env::temp_dir().join("djoc-test-html-assets");
+        fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::new(1000, 10)
+            .save(dir.join("a.png"))
+            .unwrap();
+
+        let mut document = Document::default();
+        document.text("![A](a.png)");
+        document.paths = vec![Some(dir.join("main.dj"))];
+        let parsed = document.parse();
+        let dests = |assets: &Assets| -> Vec<_> {
+            assets
+                .rewrite(0, parsed.events(0))
+                .filter_map(|event| match event {
+                    Event::Start(Container::Image(dest, _), attrs) => Some((
+                        dest.to_string(),
+                        attrs.get("srcset").map(ToString::to_string),
+                    )),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut assets = Assets::new(&parsed);
+        assets.html(AssetMode::Link, &dir, &dir, true).unwrap();
+        assert_eq!(dests(&assets), [("a.png".to_string(), None)]);
+
+        let mut assets = Assets::new(&parsed);
+        assets.html(AssetMode::Embed, &dir, &dir, true).unwrap();
+        let (dest, srcset) = dests(&assets).remove(0);
+        assert!(dest.starts_with("data:image/png;base64,iVBOR"));
+        assert_eq!(srcset, None);
+
+        // The HTML file is written to `out`, not the working directory
+        let out = dir.join("out");
+        let mut assets = Assets::new(&parsed);
+        assets
+            .html(AssetMode::Copy, &out, Path::new("static"), true)
+            .unwrap();
+        let (dest, srcset) = dests(&assets).remove(0);
+        let stem = Path::new(&dest).file_stem().unwrap().to_string_lossy();
+        assert_eq!(Path::new(&dest).parent(), Some(Path::new("static")));
+        assert!(out.join(&dest).is_file());
+        assert!(out.join(format!("static/{stem}-960w.png")).is_file());
+        let srcset = srcset.unwrap();
+        assert!(srcset.starts_with("static/"));
+        assert!(srcset.ends_with(&format!("-960w.png 960w, {dest} 1000w")));
+    }
+
+    #[cfg(feature = "latex")]
+    #[test]
+    fn test_convert_svgs() {
+        let dir = std::env::temp_dir().join("djoc-test-svg");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.svg"),
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><rect width="5" height="5"/></svg>"#,
+        )
+        .unwrap();
+
+        let mut document = Document::default();
+        document.text("![A](a.svg)");
+        document.paths = vec![Some(dir.join("main.dj"))];
+        let mut assets = Assets::new(&document.parse());
+        assets.convert_svgs(&dir).unwrap();
+        let pdf = &assets.assets[0].path;
+        assert_eq!(pdf.parent(), Some(dir.join(SVG_CACHE_DIR).as_path()));
+        assert!(fs::read(pdf).unwrap().starts_with(b"%PDF"));
+    }
+}
@@ -9,6 +9,10 @@ pub struct Author {
     pub email: Option<String>,
     /// The affiliation of the author.
     pub affiliation: Option<String>,
+    /// The ORCID iD of the author, e.g. `0000-0002-1825-0097`.
+    pub orcid: Option<String>,
+    /// Whether the author is the corresponding author.
+    pub corresponding: bool,
 }
 
 impl Display for Author {
@@ -30,6 +34,50 @@ impl<S: Into<String>> From<S> for Author {
             name: name.into(),
             email: None,
             affiliation: None,
+            orcid: None,
+            corresponding: false,
         }
     }
 }
+
+impl Author {
+    /// Returns the number of the affiliation of the author, starting from 1,
+    /// among `affiliations`.
+    pub(crate) fn affiliation_number(&self, affiliations: &[&str]) -> Option<usize> {
+        let affiliation = self.affiliation.as_deref()?;
+        Some(affiliations.iter().position(|a| *a == affiliation)? + 1)
+    }
+}
+
+/// Returns the distinct affiliations of the authors, in the order they first
+/// appear. Affiliations are numbered by their position in this list.
+pub(crate) fn affiliations(authors: &[Author]) -> Vec<&str> {
+    let mut affiliations = Vec::new();
+    for affiliation in authors.iter().filter_map(|a| a.affiliation.as_deref()) {
+        if !affiliations.contains(&affiliation) {
+            affiliations.push(affiliation);
+        }
+    }
+    affiliations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affiliations() {
+        let mut authors: Vec<Author> = vec!["A".into(), "B".into(), "C".into(), "D".into()];
+        authors[0].affiliation = Some("X".into());
+        authors[1].affiliation = Some("Y".into());
+        authors[2].affiliation = Some("X".into());
+
+        let affiliations = affiliations(&authors);
+        assert_eq!(affiliations, ["X", "Y"]);
+        let numbers: Vec<_> = authors
+            .iter()
+            .map(|a| a.affiliation_number(&affiliations))
+            .collect();
+        assert_eq!(numbers, [Some(1), Some(2), Some(1), None]);
+    }
+}
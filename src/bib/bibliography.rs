@@ -1,17 +1,223 @@
-use std::{io, path::Path};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
-use hayagriva::Entry;
+use hayagriva::{Entry, Library};
+use serde_json::Value;
 
-use crate::{utils::find_root, walk::Walker};
+use super::{json, BibError};
+use crate::walk::{WalkOptions, Walker};
 
-pub fn get_bib_entries<P: AsRef<Path>>(path: Option<P>) -> io::Result<Vec<Entry>> {
-    let bibtex_content = match path {
-        Some(path) => Walker::new(path)?.filter_extensions(&["bib", "bibtex"]),
-        _ => Walker::new(".")?.filter_extensions(&["bib", "bibtex"]),
+const BIB_EXTENSIONS: &[&str] = &["bib", "bibtex"];
+
+/// All bibliography formats that can be read: BibLaTeX, Hayagriva YAML and
+/// CSL-JSON.
+const EXTENSIONS: &[&str] = &["bib", "bibtex", "yml", "yaml", "json"];
+
+/// Reads all bibliography entries found at `path`, which may either be a
+/// single file or a directory that is searched recursively, skipping hidden
+/// and ignored files. Defaults to the current directory if no path is given.
+///
+/// BibLaTeX (`.bib`), Hayagriva YAML (`.yml`/`.yaml`) and CSL-JSON (`.json`)
+/// files are supported and may be mixed. Keys must be unique across all files.
+pub fn get_bib_entries<P: AsRef<Path>>(path: Option<P>) -> Result<Library, BibError> {
+    match path {
+        Some(path) => read_bibliographies(&[path.as_ref().to_path_buf()], &Default::default()),
+        None => read_bibliographies(&[PathBuf::from(".")], &Default::default()),
+    }
+}
+
+/// Reads and merges the entries of all bibliography files found at the given
+/// paths, walking directories following the rules in `options`.
+pub(crate) fn read_bibliographies(
+    paths: &[PathBuf],
+    options: &WalkOptions,
+) -> Result<Library, BibError> {
+    let mut library = Library::new();
+    let mut origins: HashMap<String, PathBuf> = HashMap::new();
+
+    for file in files(paths, EXTENSIONS, options)? {
+        for entry in read_file(&file)? {
+            if let Some(first) = origins.get(entry.key()) {
+                return Err(BibError::DuplicateKey {
+                    key: entry.key().to_string(),
+                    first: first.clone(),
+                    second: file,
+                });
+            }
+            origins.insert(entry.key().to_string(), file.clone());
+            library.push(&entry);
+        }
+    }
+
+    Ok(library)
+}
+
+/// Lists the BibLaTeX files found at the given paths.
+pub(crate) fn bib_files(paths: &[PathBuf], options: &WalkOptions) -> io::Result<Vec<PathBuf>> {
+    files(paths, BIB_EXTENSIONS, options)
+}
+
+/// Lists the files with one of the given extensions found at `paths`, each
+/// file only once and in a stable order.
+fn files(
+    paths: &[PathBuf],
+    extensions: &'static [&'static str],
+    options: &WalkOptions,
+) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let mut found: Vec<_> = Walker::new(path, options)?
+            .filter_extensions(extensions)
+            .collect();
+        found.sort();
+        for file in found {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Reads the entries of a single bibliography file, choosing the format by
+/// its extension.
+fn read_file(path: &Path) -> Result<Vec<Entry>, BibError> {
+    let src = fs::read_to_string(path)?;
+    let error = |key: Option<String>, message: String| BibError::Parse {
+        path: path.to_path_buf(),
+        key,
+        message,
+    };
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yml" | "yaml") => {
+            let mapping: serde_yaml::Mapping =
+                serde_yaml::from_str(&src).map_err(|e| error(None, e.to_string()))?;
+            let mut entries = Vec::new();
+            for (key, value) in mapping {
+                let name = key.as_str().map(ToString::to_string);
+                let mut single = serde_yaml::Mapping::new();
+                single.insert(key, value);
+                let library: Library = serde_yaml::from_value(single.into())
+                    .map_err(|e| error(name, e.to_string()))?;
+                entries.extend(library);
+            }
+            Ok(entries)
+        }
+        Some("json") => {
+            let items: Vec<Value> =
+                serde_json::from_str(&src).map_err(|e| error(None, e.to_string()))?;
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let item = item
+                        .as_object()
+                        .ok_or_else(|| error(None, format!("item {} is not an object", i + 1)))?;
+                    let key = json::item_key(item)
+                        .ok_or_else(|| error(None, format!("item {} has no \"id\"", i + 1)))?;
+                    match json::item_to_library(&key, item) {
+                        Ok(library) => Ok(library.into_iter().collect::<Vec<_>>()),
+                        Err(e) => Err(error(Some(key), e.to_string())),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(|entries| entries.into_iter().flatten().collect())
+        }
+        _ => {
+            let bibliography = biblatex::Bibliography::parse(&src).map_err(|e| {
+                let line = src[..e.span.start.min(src.len())].matches('\n').count() + 1;
+                error(None, format!("line {line}: {}", e.kind))
+            })?;
+            bibliography
+                .iter()
+                .map(|entry| {
+                    Entry::try_from(entry)
+                        .map_err(|e| error(Some(entry.key.clone()), e.to_string()))
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in files {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        dir
     }
-    .map(std::fs::read_to_string)
-    .collect::<Result<String, io::Error>>()?;
 
-    // TODO: Handle error(s)
-    Ok(hayagriva::io::from_biblatex_str(&bibtex_content).unwrap())
+    #[test]
+    fn test_mixed_sources() {
+        let dir = write_files(
+            "djoc-test-mixed-sources",
+            &[
+                (
+                    "a.bib",
+                    "@book{doe, title = {A Book}, author = {Doe, Jane}, year = {2020}}",
+                ),
+                (
+                    "b.yaml",
+                    "smith:\n  type: book\n  title: Another Book\n  author: Smith, John\n  date: 2019",
+                ),
+                (
+                    "c.json",
+                    r#"[{"id": "roe", "type": "article-journal", "title": "An Article",
+                        "author": [{"family": "Roe", "given": "Richard"}],
+                        "container-title": "Journal", "volume": 4, "page": "1-10",
+                        "issued": {"date-parts": [[2021, 3]]}, "DOI": "10.1000/1"}]"#,
+                ),
+            ],
+        );
+
+        let library = get_bib_entries(Some(&dir)).unwrap();
+        let keys: Vec<_> = library.keys().collect();
+        assert_eq!(keys, ["doe", "smith", "roe"]);
+
+        let roe = library.get("roe").unwrap();
+        assert_eq!(roe.authors().unwrap()[0].name, "Roe");
+        assert_eq!(roe.date().unwrap().month, Some(2));
+        assert_eq!(roe.doi(), Some("10.1000/1"));
+        assert_eq!(
+            roe.parents()[0].title().unwrap().value.to_string(),
+            "Journal"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = write_files(
+            "djoc-test-duplicate-key",
+            &[
+                ("a.bib", "@book{doe, title = {A Book}, year = {2020}}"),
+                ("b.json", r#"[{"id": "doe", "type": "book"}]"#),
+            ],
+        );
+        assert!(matches!(
+            get_bib_entries(Some(&dir)),
+            Err(BibError::DuplicateKey { key, .. }) if key == "doe"
+        ));
+
+        let dir = write_files(
+            "djoc-test-invalid-entry",
+            &[(
+                "a.yml",
+                "good:\n  type: book\nbad:\n  type: book\n  date: [not a date]",
+            )],
+        );
+        assert!(matches!(
+            get_bib_entries(Some(&dir)),
+            Err(BibError::Parse { key: Some(key), .. }) if key == "bad"
+        ));
+    }
 }
@@ -0,0 +1,361 @@
+//! Recognition of citations in a stream of Djot events.
+//!
+//! Two syntaxes are supported: Pandoc-style citations in text (`[@doe]`,
+//! `[@doe, p. 3; @smith]` and narrative `@doe`), and spans with a `cite`
+//! attribute (`[]{cite=doe}`), where the span content is replaced by the
+//! formatted citation.
+
+use std::{
+    borrow::Cow,
+    collections::{HashSet, VecDeque},
+    iter::Peekable,
+};
+
+use jotdown::{Container, Event};
+
+use crate::xref;
+
+/// A reference to a single bibliography entry within a citation.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct CiteItem {
+    pub key: String,
+    pub locator: Option<String>,
+}
+
+/// A citation as written in the source, possibly referring to several entries.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Citation {
+    pub items: Vec<CiteItem>,
+    /// Whether the citation is part of the running text (`@doe says...`.)
+    pub narrative: bool,
+}
+
+pub(crate) enum Piece<'s> {
+    Event(Event<'s>),
+    /// A citation, along with the events it was parsed from.
+    Cite(Citation, Vec<Event<'s>>),
+}
+
+/// Iterator adapter that splits citations out of an event stream.
+///
+/// Bracketed citations are always recognized, while narrative citations are
+/// only recognized if the key is one of `keys`, to avoid treating e.g. email
+/// addresses as citations.
+pub(crate) struct Split<'a, 's, I: Iterator<Item = Event<'s>>> {
+    inner: Peekable<I>,
+    keys: &'a HashSet<String>,
+    buf: VecDeque<Piece<'s>>,
+    verbatim: usize,
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Split<'a, 's, I> {
+    pub fn new(inner: I, keys: &'a HashSet<String>) -> Self {
+        Self {
+            inner: inner.peekable(),
+            keys,
+            buf: VecDeque::new(),
+            verbatim: 0,
+        }
+    }
+
+    /// Splits the citations out of a run of text events. The run is searched
+    /// as a whole, since smart punctuation such as the dash in `pp. 3--5` is
+    /// parsed into events of its own.
+    fn split_run(&mut self, events: Vec<Event<'s>>) {
+        let mut s = String::new();
+        let mut starts = Vec::with_capacity(events.len());
+        for event in &events {
+            starts.push(s.len());
+            s.push_str(text(event).unwrap_or_default());
+        }
+
+        let keys = self.keys;
+        let mut last = 0;
+        while let Some((start, end, citation)) = find_citation(&s[last..], keys) {
+            let (start, end) = (last + start, last + end);
+            let before = slice(&events, &starts, last, start);
+            self.buf.extend(before.into_iter().map(Piece::Event));
+            let original = slice(&events, &starts, start, end);
+            self.buf.push_back(Piece::Cite(citation, original));
+            last = end;
+        }
+
+        if last == 0 {
+            self.buf.extend(events.into_iter().map(Piece::Event));
+        } else {
+            let after = slice(&events, &starts, last, s.len());
+            self.buf.extend(after.into_iter().map(Piece::Event));
+        }
+    }
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Iterator for Split<'a, 's, I> {
+    type Item = Piece<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(piece) = self.buf.pop_front() {
+                return Some(piece);
+            }
+
+            let event = self.inner.next()?;
+            match event {
+                Event::Start(ref c, _) | Event::End(ref c) if is_verbatim(c) => {
+                    match event {
+                        Event::Start(..) => self.verbatim += 1,
+                        _ => self.verbatim = self.verbatim.saturating_sub(1),
+                    }
+                    return Some(Piece::Event(event));
+                }
+                Event::Start(Container::Span, ref attrs) if self.verbatim == 0 => {
+                    let Some(keys) = attrs.get("cite").map(ToString::to_string) else {
+                        return Some(Piece::Event(event));
+                    };
+
+                    let mut events = vec![event];
+                    let mut depth = 1;
+                    for event in self.inner.by_ref() {
+                        match event {
+                            Event::Start(Container::Span, _) => depth += 1,
+                            Event::End(Container::Span) => depth -= 1,
+                            _ => {}
+                        }
+                        events.push(event);
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+
+                    let items = keys
+                        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                        .map(|key| key.trim_start_matches('@'))
+                        .filter(|key| !key.is_empty())
+                        .map(|key| CiteItem {
+                            key: key.to_string(),
+                            locator: None,
+                        })
+                        .collect();
+
+                    return Some(Piece::Cite(
+                        Citation {
+                            items,
+                            narrative: false,
+                        },
+                        events,
+                    ));
+                }
+                event if self.verbatim == 0 && text(&event).is_some() => {
+                    let mut events = vec![event];
+                    while let Some(event) = self.inner.next_if(|e| text(e).is_some()) {
+                        events.push(event);
+                    }
+                    self.split_run(events);
+                }
+                event => return Some(Piece::Event(event)),
+            }
+        }
+    }
+}
+
+/// Returns the text of an event that citations are searched in, with smart
+/// punctuation as the characters it stands for.
+fn text<'e>(event: &'e Event) -> Option<&'e str> {
+    Some(match event {
+        Event::Str(s) => s.as_ref(),
+        Event::LeftSingleQuote => "‘",
+        Event::RightSingleQuote => "’",
+        Event::LeftDoubleQuote => "“",
+        Event::RightDoubleQuote => "”",
+        Event::Ellipsis => "…",
+        Event::EnDash => "–",
+        Event::EmDash => "—",
+        Event::NonBreakingSpace => "\u{a0}",
+        Event::Softbreak => " ",
+        _ => return None,
+    })
+}
+
+/// Returns the events that make up the text from `start` to `end` of a run
+/// whose events start at `starts`, cutting the strings at either end.
+/// Citations start and end within strings, so other events are never cut.
+fn slice<'s>(events: &[Event<'s>], starts: &[usize], start: usize, end: usize) -> Vec<Event<'s>> {
+    events
+        .iter()
+        .zip(starts)
+        .filter_map(|(event, &from)| {
+            let to = from + text(event).unwrap_or_default().len();
+            let (a, b) = (start.max(from), end.min(to));
+            if a >= b {
+                return None;
+            }
+            Some(match event {
+                Event::Str(s) if (a, b) != (from, to) => Event::Str(match s {
+                    Cow::Borrowed(s) => Cow::Borrowed(&s[a - from..b - from]),
+                    Cow::Owned(s) => Cow::Owned(s[a - from..b - from].to_string()),
+                }),
+                event => event.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Containers whose text content should never be searched for citations.
+fn is_verbatim(c: &Container) -> bool {
+    matches!(
+        c,
+        Container::Verbatim
+            | Container::CodeBlock { .. }
+            | Container::Math { .. }
+            | Container::RawBlock { .. }
+            | Container::RawInline { .. }
+            | Container::LinkDefinition { .. }
+            | Container::Image(..)
+    )
+}
+
+/// Finds the first citation in `s`, returning its byte range and the parsed
+/// citation.
+fn find_citation(s: &str, keys: &HashSet<String>) -> Option<(usize, usize, Citation)> {
+    let mut prev = None;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => {
+                if let Some(len) = s[i..].find(']') {
+                    if let Some(items) = parse_items(&s[i + 1..i + len]) {
+                        let citation = Citation {
+                            items,
+                            narrative: false,
+                        };
+                        return Some((i, i + len + 1, citation));
+                    }
+                }
+            }
+            '@' if !prev.is_some_and(is_key_char) => {
+                let key = parse_key(&s[i + 1..]);
+                if keys.contains(key) {
+                    let citation = Citation {
+                        items: vec![CiteItem {
+                            key: key.to_string(),
+                            locator: None,
+                        }],
+                        narrative: true,
+                    };
+                    return Some((i, i + 1 + key.len(), citation));
+                }
+            }
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+/// Parses the inside of a bracketed citation, e.g. `@doe, p. 3; @smith`.
+fn parse_items(s: &str) -> Option<Vec<CiteItem>> {
+    s.split(';')
+        .map(|item| {
+            let item = item.trim().strip_prefix('@')?;
+            let key = parse_key(item);
+            if xref::is_label(key) {
+                // `[@fig:plot]` is a cross-reference, not a citation
+                return None;
+            }
+            let rest = item[key.len()..].trim();
+            let locator = match rest.strip_prefix(',') {
+                Some(locator) => Some(locator.trim().to_string()).filter(|l| !l.is_empty()),
+                None if rest.is_empty() => None,
+                None => return None,
+            };
+            (!key.is_empty()).then(|| CiteItem {
+                key: key.to_string(),
+                locator,
+            })
+        })
+        .collect()
+}
+
+/// Returns the citation key at the start of `s`.
+///
+/// Keys consist of alphanumerics and underscores, with internal punctuation
+/// such as `-`, `:` and `.` allowed.
+fn parse_key(s: &str) -> &str {
+    let end = s
+        .char_indices()
+        .find(|(_, c)| !is_key_char(*c))
+        .map_or(s.len(), |(i, _)| i);
+    s[..end].trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_')
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.' | '/' | '+')
+}
+
+#[cfg(test)]
+mod tests {
+    use jotdown::Parser;
+
+    use super::*;
+
+    fn citations(s: &str, keys: &[&str]) -> Vec<Citation> {
+        let keys = keys.iter().map(ToString::to_string).collect();
+        Split::new(Parser::new(s), &keys)
+            .filter_map(|piece| match piece {
+                Piece::Cite(citation, _) => Some(citation),
+                Piece::Event(_) => None,
+            })
+            .collect()
+    }
+
+    fn item(key: &str, locator: Option<&str>) -> CiteItem {
+        CiteItem {
+            key: key.into(),
+            locator: locator.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn test_bracketed() {
+        assert_eq!(
+            citations("As shown [@doe; @smith-2020, p. 3].", &[]),
+            vec![Citation {
+                items: vec![item("doe", None), item("smith-2020", Some("p. 3"))],
+                narrative: false,
+            }]
+        );
+        assert_eq!(
+            citations("See [@doe, pp. 3--5; @smith, \"Intro\"...].", &[]),
+            vec![Citation {
+                items: vec![
+                    item("doe", Some("pp. 3–5")),
+                    item("smith", Some("“Intro”…"))
+                ],
+                narrative: false,
+            }]
+        );
+        assert!(citations("A [link](#x) and [not @a citation].", &[]).is_empty());
+        assert!(citations("`[@doe]`", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_narrative() {
+        assert_eq!(
+            citations("@doe argues, unlike mail@doe.", &["doe"]),
+            vec![Citation {
+                items: vec![item("doe", None)],
+                narrative: true,
+            }]
+        );
+        assert!(citations("@unknown argues", &["doe"]).is_empty());
+    }
+
+    #[test]
+    fn test_span() {
+        assert_eq!(
+            citations("See [the book]{cite=\"doe smith\"}.", &[]),
+            vec![Citation {
+                items: vec![item("doe", None), item("smith", None)],
+                narrative: false,
+            }]
+        );
+    }
+}
@@ -1,17 +1,59 @@
 use std::{
+    fs,
     io::Read,
     path::{Path, PathBuf},
 };
 
-use anyhow::{anyhow, Result};
+use hayagriva::{
+    archive::ArchivedStyle,
+    citationberg::{IndependentStyle, Style},
+};
 use log::info;
 use url::Url;
 
+use super::BibError;
 use crate::utils::data_dir;
 
-pub fn get_csl(id: &str) -> Result<PathBuf> {
-    let path = Path::new(".").join(id);
-    if path.exists() {
+/// The styles that are bundled with djoc and thus always available, even
+/// offline.
+const BUNDLED_STYLES: &[(&str, ArchivedStyle)] = &[
+    ("apa", ArchivedStyle::AmericanPsychologicalAssociation),
+    (
+        "ieee",
+        ArchivedStyle::InstituteOfElectricalAndElectronicsEngineers,
+    ),
+    ("chicago-author-date", ArchivedStyle::ChicagoAuthorDate),
+    ("chicago-note-bibliography", ArchivedStyle::ChicagoNotes),
+    (
+        "chicago-fullnote-bibliography",
+        ArchivedStyle::ChicagoFullnotes,
+    ),
+    ("vancouver", ArchivedStyle::Vancouver),
+    ("nature", ArchivedStyle::Nature),
+];
+
+/// Where to look for CSL styles besides the bundled ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct StyleLookup<'a> {
+    /// A directory with CSL files, searched before the download cache.
+    pub dir: Option<&'a Path>,
+    /// If set, styles are never downloaded.
+    pub offline: bool,
+}
+
+/// Finds the path to the CSL file identified by `id`, downloading it to the
+/// cache if needed.
+///
+/// `id` is either a path to a local CSL file, a URL or the name of a style in
+/// the [citation-style-language/styles] repository (e.g. `apa`.)
+///
+/// [citation-style-language/styles]: https://github.com/citation-style-language/styles
+pub fn get_csl(id: &str) -> Result<PathBuf, BibError> {
+    find_csl(id, StyleLookup::default())
+}
+
+fn find_csl(id: &str, lookup: StyleLookup) -> Result<PathBuf, BibError> {
+    if let Some(path) = local_csl(id, lookup.dir) {
         return Ok(path);
     }
 
@@ -21,33 +63,126 @@ pub fn get_csl(id: &str) -> Result<PathBuf> {
     } else {
         // id is the filestem of a CSL file contained in the
         // citation-style-language/styles repo
-        Url::parse("https://raw.githubusercontent.com/citation-style-language/styles/master/")?
-            .join(&format!("{id}.csl"))?
+        Url::parse("https://raw.githubusercontent.com/citation-style-language/styles/master/")
+            .and_then(|url| url.join(&format!("{id}.csl")))
+            .map_err(|_| BibError::InvalidStyle(id.to_string()))?
     };
 
     let filename = url
         .path_segments()
-        .ok_or_else(|| anyhow!("Could not determine the segments of \"{url}\".\nDoes your URL point to a valid CSL file?"))?
-        .last()
-        .ok_or_else(|| anyhow!("Unable to find the filename of \"{url}\".\nDoes your URL point to a valid CSL file?"))?;
+        .and_then(|mut segments| segments.next_back())
+        .filter(|filename| !filename.is_empty())
+        .ok_or_else(|| BibError::InvalidStyle(url.to_string()))?;
 
-    let path = data_dir().join("csl").join(filename).with_extension("csl");
+    let dir = data_dir().join("csl");
+    let path = dir.join(filename).with_extension("csl");
 
     if !path.exists() {
+        if lookup.offline {
+            return Err(BibError::Offline(id.to_string()));
+        }
+
         // If a cached CSL file doesn't exist, download it
         info!(
             "Fetching {:?} from \"{url}\" ...",
             path.file_name().unwrap()
         );
-        let resp = ureq::get(url.as_str()).call()?;
+        let resp = ureq::get(url.as_str())
+            .call()
+            .map_err(|e| BibError::Fetch {
+                url: url.to_string(),
+                source: Box::new(e),
+            })?;
 
         let mut bytes = Vec::new();
         resp.into_reader()
             .take(10_000_000)
             .read_to_end(&mut bytes)?;
 
-        std::fs::write(&path, bytes)?;
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, bytes)?;
     }
 
     Ok(path)
 }
+
+/// Finds a CSL file on disk, either at the path `id` or as `{id}.csl` in the
+/// styles directory `dir`.
+fn local_csl(id: &str, dir: Option<&Path>) -> Option<PathBuf> {
+    let path = Path::new(".").join(id);
+    if path.is_file() {
+        return Some(path);
+    }
+
+    dir.map(|dir| dir.join(id).with_extension("csl"))
+        .filter(|path| path.is_file())
+}
+
+/// Loads and parses the CSL style identified by `id` (see [`get_csl`].)
+///
+/// Local files and the styles directory take precedence over the bundled
+/// styles, which in turn take precedence over the download cache. Dependent
+/// styles are resolved to their parent style.
+pub(crate) fn load_style(id: &str, lookup: StyleLookup) -> Result<IndependentStyle, BibError> {
+    let bundled = BUNDLED_STYLES
+        .iter()
+        .find(|(name, _)| *name == id)
+        .map(|(_, style)| style.get());
+
+    let style = match bundled {
+        Some(style) if local_csl(id, lookup.dir).is_none() => style,
+        _ => {
+            let xml = fs::read_to_string(find_csl(id, lookup)?)?;
+            Style::from_xml(&xml).map_err(|source| BibError::Csl {
+                id: id.to_string(),
+                source,
+            })?
+        }
+    };
+
+    match style {
+        Style::Independent(style) => Ok(style),
+        Style::Dependent(style) => {
+            // Parent links point to e.g. `http://www.zotero.org/styles/apa`,
+            // where the last segment is the name of the parent style
+            let parent = style
+                .parent_link
+                .href
+                .rsplit('/')
+                .next()
+                .filter(|parent| !parent.is_empty() && *parent != id)
+                .ok_or_else(|| BibError::InvalidStyle(style.parent_link.href.clone()))?;
+            load_style(parent, lookup)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_lookup() {
+        let offline = StyleLookup {
+            dir: None,
+            offline: true,
+        };
+        for (name, _) in BUNDLED_STYLES {
+            assert!(load_style(name, offline).is_ok(), "{name} is not bundled");
+        }
+        assert!(matches!(
+            load_style("djoc-no-such-style", offline),
+            Err(BibError::Offline(_))
+        ));
+
+        let dir = std::env::temp_dir().join("djoc-test-csl-dir");
+        fs::create_dir_all(&dir).unwrap();
+        let style = ArchivedStyle::Nature.get();
+        fs::write(dir.join("house-style.csl"), style.to_xml().unwrap()).unwrap();
+        let lookup = StyleLookup {
+            dir: Some(&dir),
+            offline: true,
+        };
+        assert!(load_style("house-style", lookup).is_ok());
+    }
+}
@@ -0,0 +1,196 @@
+//! Conversion of [CSL-JSON] items, as exported by e.g. Zotero, to Hayagriva
+//! entries.
+//!
+//! Items are translated to the structure of Hayagriva's YAML format, which is
+//! then deserialized by Hayagriva itself.
+//!
+//! [CSL-JSON]: https://citeproc-js.readthedocs.io/en/latest/csl-json/markup.html
+
+use hayagriva::Library;
+use serde_json::{Map, Value};
+
+/// Converts a single CSL-JSON item to a [`Library`] containing one entry.
+pub(crate) fn item_to_library(key: &str, item: &Map<String, Value>) -> serde_json::Result<Library> {
+    let mut library = Map::new();
+    library.insert(key.to_string(), Value::Object(convert(item)));
+    serde_json::from_value(Value::Object(library))
+}
+
+/// Returns the citation key of a CSL-JSON item.
+pub(crate) fn item_key(item: &Map<String, Value>) -> Option<String> {
+    match item.get("id")? {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+fn convert(item: &Map<String, Value>) -> Map<String, Value> {
+    let csl_type = item.get("type").and_then(Value::as_str).unwrap_or_default();
+    let (entry_type, parent_type) = entry_types(csl_type);
+
+    let mut entry = Map::new();
+    let mut parent = Map::new();
+    entry.insert("type".into(), entry_type.into());
+    if let Some(parent_type) = parent_type {
+        parent.insert("type".into(), parent_type.into());
+    }
+
+    let string = |field: &str| match item.get(field)? {
+        Value::String(s) => Some(Value::String(s.clone())),
+        Value::Number(n) => Some(Value::String(n.to_string())),
+        _ => None,
+    };
+
+    for (csl, hayagriva) in [
+        ("title", "title"),
+        ("publisher", "publisher"),
+        ("publisher-place", "location"),
+        ("edition", "edition"),
+        ("page", "page-range"),
+        ("genre", "genre"),
+        ("note", "note"),
+        ("abstract", "abstract"),
+        ("archive", "archive"),
+        ("archive_location", "archive-location"),
+        ("call-number", "call-number"),
+    ] {
+        if let Some(value) = string(csl) {
+            entry.insert(hayagriva.into(), value);
+        }
+    }
+
+    // Volume and issue belong to the containing journal or book, if any
+    let target = match parent_type {
+        Some(_) => &mut parent,
+        None => &mut entry,
+    };
+    for (csl, hayagriva) in [("volume", "volume"), ("issue", "issue")] {
+        if let Some(value) = string(csl) {
+            target.insert(hayagriva.into(), value);
+        }
+    }
+
+    for (csl, hayagriva) in [("author", "author"), ("editor", "editor")] {
+        if let Some(Value::Array(names)) = item.get(csl) {
+            let names: Vec<_> = names.iter().filter_map(name).collect();
+            if !names.is_empty() {
+                entry.insert(hayagriva.into(), names.into());
+            }
+        }
+    }
+
+    if let Some(date) = item.get("issued").and_then(date) {
+        entry.insert("date".into(), date);
+    }
+
+    if let Some(url) = string("URL") {
+        let url = match item.get("accessed").and_then(date) {
+            Some(accessed) => {
+                let mut map = Map::new();
+                map.insert("value".into(), url);
+                map.insert("date".into(), accessed);
+                Value::Object(map)
+            }
+            None => url,
+        };
+        entry.insert("url".into(), url);
+    }
+
+    let mut serial_numbers = Map::new();
+    for (csl, hayagriva) in [
+        ("DOI", "doi"),
+        ("ISBN", "isbn"),
+        ("ISSN", "issn"),
+        ("PMID", "pmid"),
+        ("PMCID", "pmcid"),
+    ] {
+        if let Some(value) = string(csl) {
+            serial_numbers.insert(hayagriva.into(), value);
+        }
+    }
+    if !serial_numbers.is_empty() {
+        entry.insert("serial-number".into(), Value::Object(serial_numbers));
+    }
+
+    if let Some(container) = string("container-title") {
+        parent.insert("title".into(), container);
+    }
+    if parent.keys().any(|key| key != "type") {
+        parent.entry("type").or_insert_with(|| "misc".into());
+        entry.insert("parent".into(), Value::Object(parent));
+    }
+
+    entry
+}
+
+/// Maps a CSL item type to a Hayagriva entry type, along with the type of
+/// its parent if Hayagriva models the container as a separate entry.
+fn entry_types(csl_type: &str) -> (&'static str, Option<&'static str>) {
+    match csl_type {
+        "article-journal" | "article-magazine" => ("article", Some("periodical")),
+        "article-newspaper" => ("article", Some("newspaper")),
+        "paper-conference" => ("article", Some("proceedings")),
+        "chapter" => ("chapter", Some("book")),
+        "entry" | "entry-dictionary" | "entry-encyclopedia" => ("entry", Some("reference")),
+        "post-weblog" => ("post", Some("blog")),
+        "post" => ("post", None),
+        "book" => ("book", None),
+        "report" => ("report", None),
+        "thesis" => ("thesis", None),
+        "webpage" => ("web", None),
+        "patent" => ("patent", None),
+        "legal_case" => ("case", None),
+        "legislation" | "bill" => ("legislation", None),
+        "manuscript" => ("manuscript", None),
+        "motion_picture" | "broadcast" => ("video", None),
+        "song" => ("audio", None),
+        "graphic" => ("artwork", None),
+        "dataset" | "software" => ("repository", None),
+        "article" => ("article", None),
+        _ => ("misc", None),
+    }
+}
+
+/// Converts a CSL-JSON name variable to a Hayagriva person.
+fn name(name: &Value) -> Option<Value> {
+    let name = name.as_object()?;
+    if let Some(literal) = name.get("literal") {
+        return Some(literal.clone());
+    }
+
+    let mut person = Map::new();
+    person.insert("name".into(), name.get("family")?.clone());
+    for (csl, hayagriva) in [
+        ("given", "given-name"),
+        ("non-dropping-particle", "prefix"),
+        ("suffix", "suffix"),
+    ] {
+        if let Some(value) = name.get(csl) {
+            person.insert(hayagriva.into(), value.clone());
+        }
+    }
+    Some(Value::Object(person))
+}
+
+/// Converts a CSL-JSON date variable to an ISO 8601 date string.
+fn date(date: &Value) -> Option<Value> {
+    let date = date.as_object()?;
+    let parts = date.get("date-parts").and_then(|parts| parts.get(0));
+    let Some(Value::Array(parts)) = parts else {
+        return date.get("raw").or_else(|| date.get("literal")).cloned();
+    };
+
+    let mut parts = parts.iter().map(|part| match part {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    });
+    let year = parts.next()??;
+    let date = match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(month), Some(day)) => format!("{year:04}-{month:02}-{day:02}"),
+        (Some(month), None) => format!("{year:04}-{month:02}"),
+        _ => format!("{year:04}"),
+    };
+    Some(date.into())
+}
@@ -1,5 +1,566 @@
+//! Bibliography and citation functionality for djoc.
+//!
+//! Citations are written either as `[@key]` (with an optional locator, e.g.
+//! `[@key, p. 3]`, and several keys separated by `;`), as a narrative `@key`
+//! in the running text, or as a span with a `cite` attribute
+//! (`[]{cite=key}`.) They are resolved against the document's bibliography
+//! files and formatted by a CSL style.
+//!
+//! Bibliographies may be written in BibLaTeX, Hayagriva YAML or CSL-JSON.
+
 mod bibliography;
+mod cite;
 mod csl;
+mod json;
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io,
+    path::PathBuf,
+};
 
+pub(crate) use bibliography::bib_files;
 pub use bibliography::get_bib_entries;
+use bibliography::read_bibliographies;
+use cite::{Citation, CiteItem, Piece, Split};
 pub use csl::get_csl;
+pub(crate) use csl::StyleLookup;
+use hayagriva::{
+    citationberg::{
+        taxonomy::Locator, FontStyle, FontWeight, LocaleCode, TextDecoration, VerticalAlign,
+        XmlError,
+    },
+    BibliographyDriver, BibliographyRequest, CitationItem, CitationRequest, CitePurpose, ElemChild,
+    ElemChildren, Library, LocatorPayload, SpecificLocator,
+};
+use jotdown::{Attributes, Container, Event, LinkType, SpanLinkType};
+use log::warn;
+
+use crate::ParsedDocument;
+
+/// The CSL style used when none is specified.
+const DEFAULT_CSL: &str = "apa";
+const REFERENCES_TITLE: &str = "References";
+
+/// The citations of a document, resolved and formatted as Djot events.
+#[derive(Default)]
+pub(crate) struct Bibliography {
+    keys: HashSet<String>,
+    /// Formatted citations for each text of the document, in order of
+    /// appearance. `None` if none of the cited keys could be resolved.
+    citations: Vec<Vec<Option<Vec<Event<'static>>>>>,
+    /// The reference list, including its heading.
+    pub references: Vec<Event<'static>>,
+    /// The BibLaTeX files the bibliography was loaded from, which only the
+    /// biblatex mode of LaTeX output reads.
+    #[cfg_attr(not(feature = "latex"), allow(dead_code))]
+    pub files: Vec<PathBuf>,
+}
+
+impl Bibliography {
+    /// Loads the bibliography of a document and formats all of its citations
+    /// using the CSL style `csl`.
+    pub fn new(
+        parsed: &ParsedDocument,
+        csl: Option<&str>,
+        styles: StyleLookup,
+        locale: &str,
+    ) -> Result<Self, BibError> {
+        let document = parsed.document();
+        if document.bibliography.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let (library, keys, cited) = load(parsed)?;
+
+        let style = csl::load_style(csl.unwrap_or(DEFAULT_CSL), styles)?;
+        let locales = hayagriva::archive::locales();
+        let locale = LocaleCode(locale.replace('_', "-"));
+
+        let mut driver = BibliographyDriver::new();
+        let mut resolved = Vec::new();
+        for citation in cited.iter().flatten() {
+            let items: Vec<_> = citation
+                .items
+                .iter()
+                .filter_map(|item| match library.get(&item.key) {
+                    Some(entry) => Some(CitationItem::new(
+                        entry,
+                        item.locator.as_deref().map(locator),
+                        None,
+                        false,
+                        citation.narrative.then_some(CitePurpose::Prose),
+                    )),
+                    None => {
+                        warn!("Citation key `{}` not found in bibliography", item.key);
+                        None
+                    }
+                })
+                .collect();
+
+            resolved.push(!items.is_empty());
+            if !items.is_empty() {
+                driver.citation(CitationRequest::new(
+                    items,
+                    &style,
+                    Some(locale.clone()),
+                    &locales,
+                    None,
+                ));
+            }
+        }
+
+        let rendered = driver.finish(BibliographyRequest::new(
+            &style,
+            Some(locale.clone()),
+            &locales,
+        ));
+
+        let mut rendered_citations = rendered.citations.into_iter();
+        let mut resolved = resolved.into_iter();
+        let citations = cited
+            .iter()
+            .map(|citations| {
+                citations
+                    .iter()
+                    .map(|_| {
+                        resolved
+                            .next()
+                            .filter(|resolved| *resolved)
+                            .and_then(|_| rendered_citations.next())
+                            .map(|rendered| {
+                                let mut events = Vec::new();
+                                push_elems(&rendered.citation, &mut events);
+                                events
+                            })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut references = Vec::new();
+        if let Some(bibliography) = rendered.bibliography.filter(|b| !b.items.is_empty()) {
+            let id = Cow::Borrowed("references");
+            references.push(Event::Start(
+                Container::Section { id: id.clone() },
+                Attributes::new(),
+            ));
+            let heading = Container::Heading {
+                level: 1,
+                has_section: true,
+                id: id.clone(),
+            };
+            references.push(Event::Start(heading.clone(), Attributes::new()));
+            references.push(Event::Str(REFERENCES_TITLE.into()));
+            references.push(Event::End(heading));
+
+            for item in bibliography.items {
+                let mut attrs = Attributes::new();
+                attrs.insert("id", format!("ref-{}", item.key).into());
+                let div = Container::Div { class: "csl-entry" };
+                references.push(Event::Start(div.clone(), attrs));
+                references.push(Event::Start(Container::Paragraph, Attributes::new()));
+                if let Some(first_field) = item.first_field {
+                    push_elem(&first_field, &mut references);
+                    references.push(Event::Str(" ".into()));
+                }
+                push_elems(&item.content, &mut references);
+                references.push(Event::End(Container::Paragraph));
+                references.push(Event::End(div));
+            }
+
+            references.push(Event::End(Container::Section { id }));
+        }
+
+        Ok(Self {
+            keys,
+            citations,
+            references,
+            files: bib_files(&document.bibliography, &document.walk)?,
+        })
+    }
+
+    /// Loads the bibliography of a document and turns its citations into
+    /// biblatex commands, leaving the formatting of both the citations and the
+    /// reference list to biblatex.
+    pub fn biblatex(parsed: &ParsedDocument) -> Result<Self, BibError> {
+        let document = parsed.document();
+        if document.bibliography.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let (library, keys, cited) = load(parsed)?;
+
+        let citations = cited
+            .iter()
+            .map(|citations| {
+                citations
+                    .iter()
+                    .map(|citation| {
+                        let items: Vec<_> = citation
+                            .items
+                            .iter()
+                            .filter(|item| {
+                                let found = library.get(&item.key).is_some();
+                                if !found {
+                                    warn!("Citation key `{}` not found in bibliography", item.key);
+                                }
+                                found
+                            })
+                            .collect();
+                        (!items.is_empty()).then(|| biblatex_citation(citation.narrative, &items))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            keys,
+            citations,
+            references: raw_latex(
+                Container::RawBlock { format: "latex" },
+                r"\printbibliography".into(),
+            )
+            .into(),
+            files: bib_files(&document.bibliography, &document.walk)?,
+        })
+    }
+
+    /// Replaces the citations in the events of the text with index `index` by
+    /// their formatted form. Citations that could not be resolved are left as
+    /// they were written.
+    pub fn cite<'a, 's, I>(&'a self, index: usize, events: I) -> Cite<'a, 's, I>
+    where
+        I: Iterator<Item = Event<'s>>,
+    {
+        Cite {
+            inner: Split::new(events, &self.keys),
+            citations: self
+                .citations
+                .get(index)
+                .map_or(&[][..], Vec::as_slice)
+                .iter(),
+            pending: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// The citations found in each text of a document.
+type Cited = Vec<Vec<Citation>>;
+
+/// Loads the entries of all bibliographies of a document, along with the
+/// citations in each of its texts.
+fn load(parsed: &ParsedDocument) -> Result<(Library, HashSet<String>, Cited), BibError> {
+    let document = parsed.document();
+    let library = read_bibliographies(&document.bibliography, &document.walk)?;
+    let keys: HashSet<String> = library.keys().map(Into::into).collect();
+
+    let cited = (0..parsed.len())
+        .map(|i| {
+            Split::new(parsed.events(i), &keys)
+                .filter_map(|piece| match piece {
+                    Piece::Cite(citation, _) => Some(citation),
+                    Piece::Event(_) => None,
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok((library, keys, cited))
+}
+
+/// Produces the events for a biblatex citation command, e.g.
+/// `\autocite[3]{doe}` or `\textcite{doe}`.
+fn biblatex_citation(narrative: bool, items: &[&CiteItem]) -> Vec<Event<'static>> {
+    let command = match narrative {
+        true => "textcite",
+        false => "autocite",
+    };
+    let raw = |s: String| raw_latex(Container::RawInline { format: "latex" }, s);
+
+    if items.iter().all(|item| item.locator.is_none()) {
+        let keys: Vec<_> = items.iter().map(|item| item.key.as_str()).collect();
+        return raw(format!(r"\{command}{{{}}}", keys.join(","))).into();
+    }
+
+    let mut events = Vec::new();
+    match items.len() {
+        1 => events.extend(raw(format!(r"\{command}"))),
+        _ => events.extend(raw(format!(r"\{command}s"))),
+    }
+    for item in items {
+        events.extend(raw("[".into()));
+        if let Some(ref locator) = item.locator {
+            // biblatex adds the page prefix to numeric postnotes by itself
+            let postnote = match self::locator(locator) {
+                SpecificLocator(Locator::Page, LocatorPayload::Str(page)) => page,
+                _ => locator,
+            };
+            events.push(Event::Str(postnote.to_string().into()));
+        }
+        events.extend(raw(format!("]{{{}}}", item.key)));
+    }
+    events
+}
+
+fn raw_latex(container: Container<'static>, s: String) -> [Event<'static>; 3] {
+    [
+        Event::Start(container.clone(), Attributes::new()),
+        Event::Str(s.into()),
+        Event::End(container),
+    ]
+}
+
+/// Iterator returned by [`Bibliography::cite`].
+pub(crate) struct Cite<'a, 's, I: Iterator<Item = Event<'s>>> {
+    inner: Split<'a, 's, I>,
+    citations: std::slice::Iter<'a, Option<Vec<Event<'static>>>>,
+    pending: std::vec::IntoIter<Event<'s>>,
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Iterator for Cite<'a, 's, I> {
+    type Item = Event<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.next() {
+                return Some(event);
+            }
+
+            match self.inner.next()? {
+                Piece::Event(event) => return Some(event),
+                Piece::Cite(_, original) => {
+                    self.pending = match self.citations.next() {
+                        Some(Some(events)) => events.clone(),
+                        _ => original,
+                    }
+                    .into_iter()
+                }
+            }
+        }
+    }
+}
+
+/// Parses a locator such as `p. 3` or `chap. 2`.
+fn locator(s: &str) -> SpecificLocator<'_> {
+    let (label, value) = s.split_once(char::is_whitespace).unwrap_or(("", s));
+    let locator = match label.to_lowercase().as_str() {
+        "p." | "pp." | "page" | "pages" => Locator::Page,
+        "ch." | "chap." | "chapter" => Locator::Chapter,
+        "sec." | "section" | "§" => Locator::Section,
+        "fig." | "figure" => Locator::Figure,
+        "vol." | "volume" => Locator::Volume,
+        "l." | "ll." | "line" => Locator::Line,
+        "para." | "paragraph" => Locator::Paragraph,
+        _ if s.starts_with(|c: char| c.is_ascii_digit()) => {
+            return SpecificLocator(Locator::Page, LocatorPayload::Str(s))
+        }
+        _ => return SpecificLocator(Locator::Custom, LocatorPayload::Str(s)),
+    };
+    SpecificLocator(locator, LocatorPayload::Str(value.trim()))
+}
+
+/// Converts formatted output from hayagriva into Djot events.
+fn push_elems(children: &ElemChildren, events: &mut Vec<Event<'static>>) {
+    children.0.iter().for_each(|child| push_elem(child, events));
+}
+
+fn push_elem(child: &ElemChild, events: &mut Vec<Event<'static>>) {
+    match child {
+        ElemChild::Text(formatted) => {
+            let f = formatted.formatting;
+            let containers: Vec<Container> = [
+                (f.font_style == FontStyle::Italic).then_some(Container::Emphasis),
+                (f.font_weight == FontWeight::Bold).then_some(Container::Strong),
+                (f.text_decoration == TextDecoration::Underline).then_some(Container::Insert),
+                (f.vertical_align == VerticalAlign::Sup).then_some(Container::Superscript),
+                (f.vertical_align == VerticalAlign::Sub).then_some(Container::Subscript),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+
+            for c in &containers {
+                events.push(Event::Start(c.clone(), Attributes::new()));
+            }
+            events.push(Event::Str(formatted.text.clone().into()));
+            for c in containers.into_iter().rev() {
+                events.push(Event::End(c));
+            }
+        }
+        ElemChild::Elem(elem) => push_elems(&elem.children, events),
+        ElemChild::Markup(markup) => events.push(Event::Str(markup.clone().into())),
+        ElemChild::Link { text, url } => {
+            let link = Container::Link(url.clone().into(), LinkType::Span(SpanLinkType::Inline));
+            events.push(Event::Start(link.clone(), Attributes::new()));
+            events.push(Event::Str(text.text.clone().into()));
+            events.push(Event::End(link));
+        }
+        ElemChild::Transparent { .. } => {}
+    }
+}
+
+/// An error that can occur when loading a bibliography or a citation style.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum BibError {
+    /// An error that occurred while reading a bibliography or style file.
+    Io(io::Error),
+    /// A bibliography file could not be parsed.
+    Parse {
+        path: PathBuf,
+        /// The key of the entry that could not be read, if the error is
+        /// specific to one entry.
+        key: Option<String>,
+        message: String,
+    },
+    /// Several bibliography entries share the same key.
+    DuplicateKey {
+        key: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// The CSL style could not be parsed.
+    Csl { id: String, source: XmlError },
+    /// The CSL style could not be downloaded.
+    Fetch {
+        url: String,
+        source: Box<ureq::Error>,
+    },
+    /// The CSL style identifier does not point to a valid style.
+    InvalidStyle(String),
+    /// The CSL style is not available locally and may not be downloaded.
+    Offline(String),
+}
+
+impl From<io::Error> for BibError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Display for BibError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {e}"),
+            Self::Parse { path, key, message } => {
+                write!(f, "failed to parse bibliography {path:?}")?;
+                if let Some(key) = key {
+                    write!(f, " (entry \"{key}\")")?;
+                }
+                write!(f, ": {message}")
+            }
+            Self::DuplicateKey { key, first, second } => write!(
+                f,
+                "duplicate bibliography key \"{key}\" in {first:?} and {second:?}"
+            ),
+            Self::Csl { id, .. } => write!(f, "failed to parse CSL style \"{id}\""),
+            Self::Fetch { url, .. } => write!(f, "failed to fetch CSL style from \"{url}\""),
+            Self::InvalidStyle(id) => write!(
+                f,
+                "\"{id}\" is not a valid CSL style. Does it point to a valid CSL file?"
+            ),
+            Self::Offline(id) => write!(
+                f,
+                "CSL style \"{id}\" is neither bundled nor available locally, and downloading \
+                 is disabled. Add \"{id}.csl\" to the styles directory"
+            ),
+        }
+    }
+}
+
+impl Error for BibError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Csl { source, .. } => Some(source),
+            Self::Fetch { source, .. } => Some(source.as_ref()),
+            Self::Parse { .. }
+            | Self::DuplicateKey { .. }
+            | Self::InvalidStyle(_)
+            | Self::Offline(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use hayagriva::archive::ArchivedStyle;
+
+    use super::*;
+    use crate::Document;
+
+    #[test]
+    fn test_bibliography() {
+        let dir = std::env::temp_dir().join("djoc-test-bibliography");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("refs.bib"),
+            "@book{doe, title = {A Book}, author = {Doe, Jane}, year = {2020}}",
+        )
+        .unwrap();
+        let style = ArchivedStyle::InstituteOfElectricalAndElectronicsEngineers.get();
+        fs::write(dir.join("ieee.csl"), style.to_xml().unwrap()).unwrap();
+
+        let mut document = Document::from("As shown in [@doe, pp. 3--5] and [@missing].");
+        document.bibliography(dir.join("refs.bib"));
+        let parsed = document.parse();
+        let csl = dir.join("ieee.csl");
+        let bibliography =
+            Bibliography::new(&parsed, csl.to_str(), StyleLookup::default(), "en_US").unwrap();
+
+        let text: String = bibliography
+            .cite(0, parsed.events(0))
+            .filter_map(|e| match e {
+                Event::Str(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "As shown in [1, pp. 3–5] and [@missing].");
+
+        let references: String = bibliography
+            .references
+            .iter()
+            .filter_map(|e| match e {
+                Event::Str(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert!(references.starts_with("References[1] J. Doe"));
+    }
+
+    #[test]
+    fn test_biblatex() {
+        let dir = std::env::temp_dir().join("djoc-test-biblatex");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("refs.bib"),
+            "@book{doe, title = {A Book}, author = {Doe, Jane}, year = {2020}}",
+        )
+        .unwrap();
+
+        let mut document =
+            Document::from("@doe shows [@doe, pp. 3--5; @doe, ch. 2] and [@missing].");
+        document.bibliography(dir.join("refs.bib"));
+        let parsed = document.parse();
+        let bibliography = Bibliography::biblatex(&parsed).unwrap();
+
+        let text: String = bibliography
+            .cite(0, parsed.events(0))
+            .filter_map(|e| match e {
+                Event::Str(s) => Some(s.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            text,
+            r"\textcite{doe} shows \autocites[3–5]{doe}[ch. 2]{doe} and [@missing]."
+        );
+        assert_eq!(bibliography.files, vec![dir.join("refs.bib")]);
+    }
+}
@@ -1,8 +1,43 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
-use crate::manifest::BuilderManifest;
+use log::warn;
+use serde::Deserialize;
+
+use crate::{
+    bib::StyleLookup,
+    highlight::{Theme, DEFAULT_THEME},
+    manifest::BuilderManifest,
+    Document, DocumentType, TopLevelDivision,
+};
 
 const DEFAULT_LOCALE: &str = "en_US";
+const DEFAULT_ASSET_DIR: &str = "assets";
+const DEFAULT_TOC_DEPTH: u16 = 3;
+
+/// How images are referenced in HTML output.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AssetMode {
+    /// Images are linked by their destination as written in the text, so
+    /// they are relative to the HTML file.
+    #[default]
+    Link,
+    /// Images are embedded as base64 data URIs, so that the HTML file is
+    /// self-contained.
+    Embed,
+    /// Images are copied into the asset directory (see [`Builder::asset_dir`])
+    /// under a hash of their content.
+    Copy,
+}
+
+/// A stylesheet or script added to HTML output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resource {
+    /// Referred to by its URL, or its path relative to the HTML file.
+    Link(String),
+    /// Read from a file and inlined into the HTML file.
+    Inline(PathBuf),
+}
 
 // NOTE: Allow dead code to avoid compiler warnings when all features are
 // disabled
@@ -17,6 +52,28 @@ pub struct Builder {
     pub(crate) locale: String,
     pub(crate) add_title: bool,
     pub(crate) standalone: bool,
+    pub(crate) csl: Option<String>,
+    pub(crate) csl_dir: Option<PathBuf>,
+    pub(crate) offline: bool,
+    pub(crate) biblatex: bool,
+    pub(crate) biblatex_style: Option<String>,
+    pub(crate) highlight_theme: Option<String>,
+    pub(crate) top_level_division: Option<TopLevelDivision>,
+    pub(crate) symbols: HashMap<String, String>,
+    pub(crate) emoji_font: Option<String>,
+    pub(crate) latex_template: Option<PathBuf>,
+    pub(crate) asset_mode: AssetMode,
+    pub(crate) asset_dir: PathBuf,
+    pub(crate) output_dir: Option<PathBuf>,
+    pub(crate) srcset: bool,
+    pub(crate) html_template: Option<PathBuf>,
+    pub(crate) stylesheets: Vec<Resource>,
+    pub(crate) scripts: Vec<Resource>,
+    pub(crate) default_css: bool,
+    pub(crate) toc: bool,
+    pub(crate) toc_depth: u16,
+    pub(crate) lof: bool,
+    pub(crate) lot: bool,
 }
 
 impl Default for Builder {
@@ -27,6 +84,28 @@ impl Default for Builder {
             locale: DEFAULT_LOCALE.to_string(),
             add_title: false,
             standalone: true,
+            csl: None,
+            csl_dir: None,
+            offline: false,
+            biblatex: false,
+            biblatex_style: None,
+            highlight_theme: None,
+            top_level_division: None,
+            symbols: HashMap::new(),
+            emoji_font: None,
+            latex_template: None,
+            asset_mode: AssetMode::default(),
+            asset_dir: PathBuf::from(DEFAULT_ASSET_DIR),
+            output_dir: None,
+            srcset: false,
+            html_template: None,
+            stylesheets: Vec::new(),
+            scripts: Vec::new(),
+            default_css: true,
+            toc: false,
+            toc_depth: DEFAULT_TOC_DEPTH,
+            lof: false,
+            lot: false,
         }
     }
 }
@@ -39,9 +118,40 @@ impl Builder {
             build_dir: manifest.build_dir.clone(),
             add_title: manifest.add_title.unwrap_or(false),
             standalone: true,
+            csl: manifest.csl.clone(),
+            csl_dir: manifest.csl_dir.clone(),
+            offline: manifest.offline.unwrap_or(false),
+            biblatex: manifest.biblatex.unwrap_or(false),
+            biblatex_style: manifest.biblatex_style.clone(),
+            highlight_theme: manifest.highlight_theme.clone(),
+            top_level_division: manifest.top_level_division,
+            symbols: manifest.symbols.clone(),
+            emoji_font: manifest.emoji_font.clone(),
+            latex_template: manifest.latex_template.clone(),
+            asset_mode: AssetMode::default(),
+            asset_dir: manifest
+                .asset_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_ASSET_DIR)),
+            output_dir: None,
+            srcset: false,
+            html_template: None,
+            stylesheets: Vec::new(),
+            scripts: Vec::new(),
+            default_css: true,
+            toc: manifest.toc.unwrap_or(false),
+            toc_depth: manifest.toc_depth.unwrap_or(DEFAULT_TOC_DEPTH),
+            lof: manifest.lof.unwrap_or(false),
+            lot: manifest.lot.unwrap_or(false),
         }
     }
 
+    /// Creates a builder with the build options given in the front matter of
+    /// the document (see [`Document::from_path`].)
+    pub fn for_document(document: &Document) -> Self {
+        Self::from_manifest(&document.options)
+    }
+
     /// Set whether to number sections in the built document.
     ///
     /// # Examples
@@ -59,7 +169,9 @@ impl Builder {
     /// Set the directory where any build artifacts will be placed (e.g.
     /// auxiliary files and/or logs.)
     ///
-    /// If not set, only the output will be written to disk.
+    /// If not set, only the output will be written to disk, apart from SVG
+    /// images converted for LaTeX output, which are cached in a temporary
+    /// directory.
     pub fn build_dir(&mut self, build_dir: PathBuf) -> &mut Self {
         self.build_dir = Some(build_dir);
         self
@@ -70,6 +182,9 @@ impl Builder {
     /// All locales present in the [`pure-rust-locales`] crate are supported. In
     /// general, most [BCP 47] language tags are supported.
     ///
+    /// The locale determines the language of the document, the format of its
+    /// date and the quotation marks, e.g. „…“ for `de_DE` or «…» for `nb_NO`.
+    ///
     /// [`pure-rust-locales`]: https://docs.rs/pure-rust-locales
     /// [BCP 47]: https://tools.ietf.org/html/bcp47
     pub fn locale(&mut self, locale: impl Into<String>) -> &mut Self {
@@ -86,4 +201,209 @@ impl Builder {
         self.add_title = add_title;
         self
     }
+
+    /// Sets the CSL style used to format citations and the reference list.
+    ///
+    /// The style can be given as a path to a CSL file, a URL or the name of a
+    /// style in the [CSL style repository] (e.g. `ieee`.) Defaults to `apa`.
+    ///
+    /// The styles `apa`, `ieee`, `chicago-author-date`,
+    /// `chicago-note-bibliography`, `chicago-fullnote-bibliography`,
+    /// `vancouver` and `nature` are bundled with djoc, other styles are looked
+    /// up in [`Builder::csl_dir`] and otherwise downloaded.
+    ///
+    /// [CSL style repository]: https://github.com/citation-style-language/styles
+    pub fn csl(&mut self, csl: impl Into<String>) -> &mut Self {
+        self.csl = Some(csl.into());
+        self
+    }
+
+    /// Sets a directory containing CSL files. A style named `name` is looked up
+    /// as `name.csl` in this directory before the bundled styles and the
+    /// download cache.
+    pub fn csl_dir(&mut self, csl_dir: PathBuf) -> &mut Self {
+        self.csl_dir = Some(csl_dir);
+        self
+    }
+
+    /// Set whether djoc may access the network, e.g. to download CSL styles.
+    ///
+    /// In offline mode, only bundled, local and previously cached styles can
+    /// be used, and an error is returned for any other style.
+    pub fn offline(&mut self, offline: bool) -> &mut Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Set whether LaTeX and PDF output should leave citations to biblatex.
+    ///
+    /// If enabled, citations are written as `\autocite` and `\textcite`
+    /// commands, the bibliography files are added with `\addbibresource` and
+    /// the reference list is printed with `\printbibliography`, instead of
+    /// being formatted by a CSL style. HTML output is not affected.
+    pub fn biblatex(&mut self, biblatex: bool) -> &mut Self {
+        self.biblatex = biblatex;
+        self
+    }
+
+    /// Sets the biblatex style (e.g. `authoryear` or `ieee`) used when
+    /// [`Builder::biblatex`] is enabled. If not set, biblatex's default style
+    /// is used.
+    pub fn biblatex_style(&mut self, style: impl Into<String>) -> &mut Self {
+        self.biblatex_style = Some(style.into());
+        self
+    }
+
+    /// Sets the color theme used to highlight code blocks. The themes are the
+    /// ones bundled with syntect: `inspired-github` (the default),
+    /// `solarized-light`, `solarized-dark`, `base16-ocean-light`,
+    /// `base16-ocean-dark`, `base16-eighties-dark` and `base16-mocha-dark`.
+    pub fn highlight_theme(&mut self, theme: impl Into<String>) -> &mut Self {
+        self.highlight_theme = Some(theme.into());
+        self
+    }
+
+    /// Sets what level 1 headings are: parts, chapters or sections. If not
+    /// set, it is determined by the [`DocumentType`] of the document, with
+    /// chapters for reports and books and sections for articles.
+    pub fn top_level_division(&mut self, division: TopLevelDivision) -> &mut Self {
+        self.top_level_division = Some(division);
+        self
+    }
+
+    /// Defines a symbol, so that `:name:` is replaced by `value`. User-defined
+    /// symbols take precedence over the built-in emoji and glyphs.
+    pub fn symbol(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.symbols.insert(name.into(), value.into());
+        self
+    }
+
+    /// Sets the font used for emoji in LaTeX and PDF output. Defaults to
+    /// `Noto Emoji`. Note that XeTeX, which is used to build PDFs, cannot
+    /// render color fonts.
+    pub fn emoji_font(&mut self, font: impl Into<String>) -> &mut Self {
+        self.emoji_font = Some(font.into());
+        self
+    }
+
+    /// Sets the template that standalone LaTeX and PDF output is written
+    /// with. If not set, the built-in template is used, which can be printed
+    /// with `djoc template --print latex` as a starting point.
+    pub fn latex_template(&mut self, template: impl Into<PathBuf>) -> &mut Self {
+        self.latex_template = Some(template.into());
+        self
+    }
+
+    /// Sets how images are referenced in HTML output: linked by their path
+    /// (the default), embedded as data URIs or copied into the asset
+    /// directory.
+    pub fn asset_mode(&mut self, mode: AssetMode) -> &mut Self {
+        self.asset_mode = mode;
+        self
+    }
+
+    /// Sets the directory that images are copied to in [`AssetMode::Copy`],
+    /// relative to the output directory. The HTML output refers to the copies
+    /// by this path. Defaults to `assets`.
+    pub fn asset_dir(&mut self, asset_dir: PathBuf) -> &mut Self {
+        self.asset_dir = asset_dir;
+        self
+    }
+
+    /// Sets the directory that the output is written to, which the asset
+    /// directory is relative to. Defaults to the working directory.
+    pub fn output_dir(&mut self, output_dir: impl Into<PathBuf>) -> &mut Self {
+        self.output_dir = Some(output_dir.into());
+        self
+    }
+
+    /// Set whether copied PNG and JPEG images get downscaled variants, which
+    /// are offered to the browser in a `srcset`. Only applies to
+    /// [`AssetMode::Copy`].
+    pub fn srcset(&mut self, srcset: bool) -> &mut Self {
+        self.srcset = srcset;
+        self
+    }
+
+    /// Sets the template that standalone HTML output is written with. If not
+    /// set, the built-in template is used, which can be printed with `djoc
+    /// template --print html` as a starting point.
+    pub fn html_template(&mut self, template: impl Into<PathBuf>) -> &mut Self {
+        self.html_template = Some(template.into());
+        self
+    }
+
+    /// Adds a stylesheet to HTML output, after the built-in CSS.
+    pub fn stylesheet(&mut self, stylesheet: Resource) -> &mut Self {
+        self.stylesheets.push(stylesheet);
+        self
+    }
+
+    /// Adds a script to HTML output.
+    pub fn script(&mut self, script: Resource) -> &mut Self {
+        self.scripts.push(script);
+        self
+    }
+
+    /// Set whether HTML output includes the built-in CSS, i.e. the default
+    /// style, the highlighting theme and the KaTeX stylesheet. Enabled by
+    /// default; disable it to style the output with
+    /// [`Builder::stylesheet`] alone.
+    pub fn default_css(&mut self, default_css: bool) -> &mut Self {
+        self.default_css = default_css;
+        self
+    }
+
+    /// Set whether to add a table of contents after the title.
+    pub fn toc(&mut self, toc: bool) -> &mut Self {
+        self.toc = toc;
+        self
+    }
+
+    /// Sets the level of the deepest headings in the table of contents.
+    /// Defaults to 3.
+    pub fn toc_depth(&mut self, toc_depth: u16) -> &mut Self {
+        self.toc_depth = toc_depth;
+        self
+    }
+
+    /// Set whether to add a list of the figures with a caption after the
+    /// table of contents.
+    pub fn lof(&mut self, lof: bool) -> &mut Self {
+        self.lof = lof;
+        self
+    }
+
+    /// Set whether to add a list of the tables with a caption after the table
+    /// of contents.
+    pub fn lot(&mut self, lot: bool) -> &mut Self {
+        self.lot = lot;
+        self
+    }
+
+    pub(crate) fn division(&self, document: &Document) -> TopLevelDivision {
+        match self.top_level_division {
+            Some(TopLevelDivision::Chapter) if document.document_type == DocumentType::Article => {
+                warn!("Articles have no chapters, using sections as the top-level division");
+                TopLevelDivision::Section
+            }
+            Some(division) => division,
+            None => document.document_type.top_level_division(),
+        }
+    }
+
+    pub(crate) fn theme(&self) -> &'static Theme {
+        let name = self.highlight_theme.as_deref().unwrap_or(DEFAULT_THEME);
+        Theme::find(name).unwrap_or_else(|| {
+            warn!("Unknown highlight theme `{name}`, using `{DEFAULT_THEME}`");
+            Theme::find(DEFAULT_THEME).unwrap()
+        })
+    }
+
+    pub(crate) fn style_lookup(&self) -> StyleLookup<'_> {
+        StyleLookup {
+            dir: self.csl_dir.as_deref(),
+            offline: self.offline,
+        }
+    }
 }
@@ -12,39 +12,45 @@ pub fn compile(
     output: Option<PathBuf>,
     number_sections: bool,
 ) -> Result<()> {
-    let mut builder = Builder::default();
-    builder.number_sections(number_sections);
     let doc = match path {
         Some(path) => Document::from_path(path)?,
         None => {
             let mut s = String::new();
             std::io::stdin().read_to_string(&mut s)?;
-            Document::from(s)
+            Document::from_source(s)?
         }
     };
+    let parsed = doc.parse();
+    let mut builder = Builder::for_document(&doc);
+    if number_sections {
+        builder.number_sections(true);
+    }
 
     let format = format.replace("latex", "tex");
     if let Some(output) = output {
         let file = File::create(&output)?;
         debug!("Writing to {output:?}");
+        if let Some(dir) = output.parent() {
+            builder.output_dir(dir);
+        }
         match format.as_str() {
             #[cfg(any(feature = "html", feature = "html-wasm"))]
-            "html" => builder.write_html(&doc, file)?,
+            "html" => builder.write_html(&parsed, file)?,
             #[cfg(feature = "latex")]
-            "tex" | "latex" => builder.write_latex(&doc, file)?,
+            "tex" | "latex" => builder.write_latex(&parsed, file)?,
             #[cfg(feature = "pdf")]
-            "pdf" => builder.write_pdf(&doc, file)?,
+            "pdf" => builder.write_pdf(&parsed, file)?,
             _ => bail!("Unknown format `{}`", format),
         };
     } else {
         let stdout = std::io::stdout();
         match format.as_str() {
             #[cfg(any(feature = "html", feature = "html-wasm"))]
-            "html" => builder.write_html(&doc, stdout)?,
+            "html" => builder.write_html(&parsed, stdout)?,
             #[cfg(feature = "latex")]
-            "tex" | "latex" => builder.write_latex(&doc, stdout)?,
+            "tex" | "latex" => builder.write_latex(&parsed, stdout)?,
             #[cfg(feature = "pdf")]
-            "pdf" => builder.write_pdf(&doc, stdout)?,
+            "pdf" => builder.write_pdf(&parsed, stdout)?,
             _ => bail!("Unknown format `{}`", format),
         };
     }
@@ -2,6 +2,7 @@ mod build;
 mod clean;
 mod compile;
 mod init;
+mod template;
 
 use std::path::PathBuf;
 
@@ -54,6 +55,13 @@ enum Command {
         /// Directory to initialize the document in.
         path: Option<PathBuf>,
     },
+
+    /// Prints the built-in template of an output format.
+    Template {
+        /// The format whose template to print.
+        #[arg(long)]
+        print: String,
+    },
 }
 
 pub fn run() -> Result<()> {
@@ -78,6 +86,7 @@ pub fn run() -> Result<()> {
             number_sections,
         } => compile::compile(path, format, output, number_sections)?,
         Command::Init { path } => init::init(path)?,
+        Command::Template { print } => template::print(print)?,
     }
 
     Ok(())
@@ -0,0 +1,16 @@
+use std::io::{self, Write};
+
+use anyhow::{bail, Result};
+
+/// Prints the built-in template for a format, to start a custom one from.
+pub fn print(format: String) -> Result<()> {
+    let template = match format.as_str() {
+        #[cfg(any(feature = "html", feature = "html-wasm"))]
+        "html" => djoc::html::DEFAULT_TEMPLATE,
+        #[cfg(feature = "latex")]
+        "tex" | "latex" => djoc::latex::DEFAULT_TEMPLATE,
+        _ => bail!("No template for format `{}`", format),
+    };
+    io::stdout().write_all(template.as_bytes())?;
+    Ok(())
+}
@@ -1,8 +1,19 @@
-use std::{fs, io, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
 
+use jotdown::{Container, Event, Parser};
 use serde::Deserialize;
 
-use crate::{kebab, manifest::DocumentManifest, walk::Walker, Author, Date};
+use crate::{
+    front_matter::FrontMatter,
+    has_class, kebab,
+    manifest::{BuilderManifest, DocumentManifest},
+    walk::{WalkOptions, Walker},
+    Author, Date, ParsedDocument,
+};
 
 /// Enumerates the types of documents that can be generated.
 ///
@@ -26,6 +37,17 @@ impl AsRef<str> for DocumentType {
     }
 }
 
+impl DocumentType {
+    /// Returns the default top-level division of the document type: chapters
+    /// for reports and books, and sections for articles.
+    pub fn top_level_division(self) -> TopLevelDivision {
+        match self {
+            DocumentType::Article => TopLevelDivision::Section,
+            DocumentType::Report | DocumentType::Book => TopLevelDivision::Chapter,
+        }
+    }
+}
+
 impl From<&str> for DocumentType {
     fn from(s: &str) -> Self {
         match s {
@@ -37,6 +59,36 @@ impl From<&str> for DocumentType {
     }
 }
 
+/// Enumerates the divisions that level 1 headings can be mapped to.
+///
+/// Lower level headings follow, e.g. level 2 headings are sections if level 1
+/// headings are chapters.
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TopLevelDivision {
+    Part,
+    Chapter,
+    #[default]
+    Section,
+}
+
+impl TopLevelDivision {
+    /// Returns the LaTeX sectioning depth of a heading with the given level and
+    /// classes: -1 for parts, 0 for chapters, 1 for sections and so on.
+    /// Headings with the class `part` are always parts.
+    pub(crate) fn depth(self, level: u16, classes: &str) -> i32 {
+        if has_class(classes, "part") {
+            return -1;
+        }
+        let top = match self {
+            TopLevelDivision::Part => -1,
+            TopLevelDivision::Chapter => 0,
+            TopLevelDivision::Section => 1,
+        };
+        top + i32::from(level) - 1
+    }
+}
+
 /// In-memory representation of a document.
 ///
 /// # Examples
@@ -50,11 +102,14 @@ impl From<&str> for DocumentType {
 ///     .document_type("report")
 ///     .author("John Doe")
 ///     .authors(["Jane Doe", "John Smith"])
+///     .subtitle("A Subtitle")
+///     .keywords(["djot", "typesetting"])
 ///     .text("This is the first paragraph.");
 ///
 /// assert_eq!(document.title, "My Document");
 /// assert_eq!(document.document_type, DocumentType::Report);
 /// assert_eq!(document.authors.len(), 3);
+/// assert_eq!(document.keywords.len(), 2);
 /// ```
 #[derive(Default)]
 pub struct Document {
@@ -62,32 +117,111 @@ pub struct Document {
     pub authors: Vec<Author>,
     pub date: Date,
     pub document_type: DocumentType,
+    pub subtitle: Option<String>,
+    /// The abstract of the document, as Djot text.
+    pub abstract_: Option<String>,
+    pub keywords: Vec<String>,
+    /// Acknowledgements attached to the title, as Djot text.
+    pub thanks: Option<String>,
+    /// The DOI of the document, without the `https://doi.org/` prefix.
+    pub doi: Option<String>,
+    pub license: Option<String>,
+    /// Further metadata, which templates can refer to by its key.
+    pub metadata: BTreeMap<String, String>,
     pub(crate) texts: Vec<String>,
+    /// The source file of each text, if it was loaded from one.
+    pub(crate) paths: Vec<Option<PathBuf>>,
+    pub(crate) bibliography: Vec<PathBuf>,
+    /// The build options given in the front matter of the texts.
+    pub(crate) options: BuilderManifest,
+    /// The rules for walking the directories of texts and bibliographies.
+    pub(crate) walk: WalkOptions,
 }
 
 impl Document {
-    pub(crate) fn from_manifest(manifest: &DocumentManifest) -> io::Result<Self> {
-        let mut texts = Vec::new();
+    /// Creates a document from a manifest. The fields of the manifest take
+    /// precedence over the front matter of the texts, and directories are
+    /// walked following the rules in `options`.
+    pub(crate) fn from_manifest(
+        manifest: &DocumentManifest,
+        options: &BuilderManifest,
+    ) -> io::Result<Self> {
+        let mut document = Self {
+            walk: WalkOptions::from_manifest(options),
+            ..Default::default()
+        };
         for path in &manifest.texts {
             if path.is_dir() {
-                extend_texts(path, &mut texts)?;
+                document.extend_texts(path)?;
             } else {
-                texts.push(fs::read_to_string(path)?);
+                document.push_source(fs::read_to_string(path)?, Some(path.clone()))?;
             }
         }
 
-        Ok(Self {
-            texts,
-            date: manifest.date.map(|d| d.into()).unwrap_or_default(),
-            title: manifest.title.to_owned(),
-            authors: manifest
-                .authors
-                .clone()
-                .into_iter()
-                .map(Into::into)
-                .collect(),
-            document_type: manifest.document_type,
-        })
+        document.title = manifest.title.to_owned();
+        if let Some(date) = manifest.date {
+            document.date = date.into();
+        }
+        if !manifest.authors.is_empty() {
+            document.authors = manifest.authors.clone();
+        }
+        document.document_type = manifest.document_type;
+        if let Some(ref subtitle) = manifest.subtitle {
+            document.subtitle = Some(subtitle.clone());
+        }
+        if let Some(ref abstract_) = manifest.abstract_ {
+            document.abstract_ = Some(read_abstract(abstract_, None)?);
+        }
+        if !manifest.keywords.is_empty() {
+            document.keywords = manifest.keywords.clone();
+        }
+        if let Some(ref thanks) = manifest.thanks {
+            document.thanks = Some(thanks.clone());
+        }
+        if let Some(ref doi) = manifest.doi {
+            document.doi = Some(doi.clone());
+        }
+        if let Some(ref license) = manifest.license {
+            document.license = Some(license.clone());
+        }
+        document.metadata.extend(manifest.metadata.clone());
+        document
+            .bibliography
+            .extend(manifest.bibliography.iter().cloned());
+        Ok(document)
+    }
+
+    /// Creates a document from a single Djot source, with its metadata and
+    /// build options taken from its front matter (see
+    /// [`Document::from_path`].)
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the front matter is invalid.
+    pub fn from_source(source: impl Into<String>) -> io::Result<Self> {
+        let mut document = Self::default();
+        document.push_source(source.into(), None)?;
+        document.title_from_heading();
+        Ok(document)
+    }
+
+    /// Parses the texts of the document, so that it can be written in several
+    /// formats without being parsed again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use djoc::{Builder, Document};
+    ///
+    /// let document = Document::from("Hello, world!");
+    /// let parsed = document.parse();
+    /// let builder = Builder::default();
+    /// let (mut html, mut latex) = (Vec::new(), Vec::new());
+    /// builder.write_html(&parsed, &mut html).unwrap();
+    /// builder.write_latex(&parsed, &mut latex).unwrap();
+    /// ```
+    pub fn parse(&self) -> ParsedDocument<'_> {
+        ParsedDocument::new(self)
     }
 
     /// Sets the document title.
@@ -99,6 +233,7 @@ impl Document {
     /// Adds a text to the document.
     pub fn text<T: Into<String>>(&mut self, text: T) -> &mut Self {
         self.texts.push(text.into());
+        self.paths.resize(self.texts.len(), None);
         self
     }
 
@@ -130,6 +265,65 @@ impl Document {
         self
     }
 
+    /// Sets the subtitle of the document.
+    pub fn subtitle<T: Into<String>>(&mut self, subtitle: T) -> &mut Self {
+        self.subtitle = Some(subtitle.into());
+        self
+    }
+
+    /// Sets the abstract of the document, which is Djot text.
+    pub fn abstract_<T: Into<String>>(&mut self, abstract_: T) -> &mut Self {
+        self.abstract_ = Some(abstract_.into());
+        self
+    }
+
+    /// Adds a keyword to the document.
+    pub fn keyword<T: Into<String>>(&mut self, keyword: T) -> &mut Self {
+        self.keywords.push(keyword.into());
+        self
+    }
+
+    /// Adds multiple keywords to the document.
+    pub fn keywords<T: Into<String>>(
+        &mut self,
+        keywords: impl IntoIterator<Item = T>,
+    ) -> &mut Self {
+        self.keywords.extend(keywords.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the acknowledgements attached to the title, which are Djot text.
+    pub fn thanks<T: Into<String>>(&mut self, thanks: T) -> &mut Self {
+        self.thanks = Some(thanks.into());
+        self
+    }
+
+    /// Sets the DOI of the document, e.g. `10.1000/182`.
+    pub fn doi<T: Into<String>>(&mut self, doi: T) -> &mut Self {
+        self.doi = Some(doi.into());
+        self
+    }
+
+    /// Sets the license of the document, e.g. `CC BY 4.0`.
+    pub fn license<T: Into<String>>(&mut self, license: T) -> &mut Self {
+        self.license = Some(license.into());
+        self
+    }
+
+    /// Sets a metadata field of the document, which templates can refer to
+    /// as `$key$`.
+    pub fn metadata<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> &mut Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Adds a bibliography to the document. The path can either point to a
+    /// BibLaTeX file or to a directory, which will be searched recursively.
+    pub fn bibliography<P: Into<PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.bibliography.push(path.into());
+        self
+    }
+
     /// Sets the date of the document.
     pub fn date<D: Into<Date>>(&mut self, date: D) -> &mut Self {
         self.date = date.into();
@@ -139,21 +333,121 @@ impl Document {
     /// Creates a new document from a path. If the path points to a Djot file,
     /// the document will be loaded from the file. If the path points to a
     /// directory, the directory will be recursively walked and all Djot files
-    /// will be loaded.
+    /// will be loaded in natural order (`2-intro.dj` before `10-results.dj`),
+    /// unless a directory sets the order in an `_order.toml` or `SUMMARY.dj`
+    /// index file.
+    ///
+    /// The metadata of the document and its build options can be given in
+    /// TOML front matter at the top of a file, delimited by `+++` lines or as
+    /// a raw block in the `toml` format. The front matter has the same fields
+    /// as a document in a manifest, and is removed from the text. Without a
+    /// title in the front matter, the first level 1 heading is the title.
     ///
     /// # Errors
     ///
-    /// This function will return an error if the path does not exist or if any
-    /// of the files cannot be read.
+    /// This function will return an error if the path does not exist, if any
+    /// of the files cannot be read or if any front matter is invalid.
     pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
         let path = fs::canonicalize(path)?;
-        let mut texts = Vec::new();
-        extend_texts(&path, &mut texts)?;
+        let mut document = Self::default();
+        document.extend_texts(&path)?;
+        document.title_from_heading();
+        Ok(document)
+    }
 
-        Ok(Self {
-            texts,
-            ..Default::default()
-        })
+    fn extend_texts(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        Walker::new(path, &self.walk)?
+            .filter_extensions(&["dj"])
+            .try_for_each(|path| self.push_source(fs::read_to_string(&path)?, Some(path)))
+    }
+
+    /// Adds a text read from `path`, applying its front matter.
+    fn push_source(&mut self, source: String, path: Option<PathBuf>) -> io::Result<()> {
+        let (front_matter, text) = FrontMatter::split(&source).map_err(|e| match path {
+            Some(ref path) => io::Error::new(e.kind(), format!("{path:?}: {e}")),
+            None => e,
+        })?;
+        if let Some(front_matter) = front_matter {
+            self.apply(front_matter, path.as_deref().and_then(Path::parent))?;
+        }
+        self.texts.push(text.to_string());
+        self.paths.push(path);
+        Ok(())
+    }
+
+    /// Sets the metadata and build options given in front matter. Paths are
+    /// relative to `dir`, the directory of the file.
+    fn apply(&mut self, front_matter: FrontMatter, dir: Option<&Path>) -> io::Result<()> {
+        if let Some(title) = front_matter.title {
+            self.title = title;
+        }
+        if let Some(date) = front_matter.date {
+            self.date = date.into();
+        }
+        if let Some(document_type) = front_matter.document_type {
+            self.document_type = document_type;
+        }
+        if let Some(subtitle) = front_matter.subtitle {
+            self.subtitle = Some(subtitle);
+        }
+        if let Some(abstract_) = front_matter.abstract_ {
+            self.abstract_ = Some(read_abstract(&abstract_, dir)?);
+        }
+        if let Some(thanks) = front_matter.thanks {
+            self.thanks = Some(thanks);
+        }
+        if let Some(doi) = front_matter.doi {
+            self.doi = Some(doi);
+        }
+        if let Some(license) = front_matter.license {
+            self.license = Some(license);
+        }
+        self.keywords.extend(front_matter.keywords);
+        self.metadata.extend(front_matter.metadata);
+        self.authors.extend(front_matter.authors);
+        self.bibliography.extend(
+            front_matter
+                .bibliography
+                .into_iter()
+                .map(|path| dir.map_or_else(|| path.clone(), |dir| dir.join(&path))),
+        );
+        self.options = self.options.merge(front_matter.builder);
+        Ok(())
+    }
+
+    /// Sets the title to the first level 1 heading, if there is no title.
+    fn title_from_heading(&mut self) {
+        if !self.title.is_empty() {
+            return;
+        }
+        let mut events = self.texts.iter().flat_map(|text| Parser::new(text));
+        if events
+            .find(|e| matches!(e, Event::Start(Container::Heading { level: 1, .. }, _)))
+            .is_none()
+        {
+            return;
+        }
+        for event in events {
+            match event {
+                Event::End(Container::Heading { .. }) => break,
+                Event::Str(s) => self.title.push_str(&s),
+                Event::Softbreak | Event::NonBreakingSpace => self.title.push(' '),
+                Event::LeftSingleQuote => self.title.push('‘'),
+                Event::RightSingleQuote => self.title.push('’'),
+                Event::LeftDoubleQuote => self.title.push('“'),
+                Event::RightDoubleQuote => self.title.push('”'),
+                Event::Ellipsis => self.title.push('…'),
+                Event::EnDash => self.title.push('–'),
+                Event::EmDash => self.title.push('—'),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the source file of the text at `index`, if it was loaded from
+    /// one.
+    pub(crate) fn path(&self, index: usize) -> Option<&Path> {
+        self.paths.get(index)?.as_deref()
     }
 
     /// Produces a filename for naming the output file(s).
@@ -180,11 +474,27 @@ impl<S: Into<String>> FromIterator<S> for Document {
     }
 }
 
-fn extend_texts(path: impl AsRef<Path>, texts: &mut Vec<String>) -> io::Result<()> {
-    Walker::new(path)?
-        .filter_extensions(&["dj"])
-        .try_for_each(|path| {
-            texts.push(fs::read_to_string(path)?);
-            Ok(())
-        })
+/// Reads an abstract given as the path of a file, relative to `dir`, or
+/// returns the abstract itself.
+fn read_abstract(abstract_: &str, dir: Option<&Path>) -> io::Result<String> {
+    if abstract_.contains('\n') {
+        return Ok(abstract_.to_string());
+    }
+    let path = dir.map_or_else(|| PathBuf::from(abstract_), |dir| dir.join(abstract_));
+    match path.is_file() {
+        true => fs::read_to_string(path),
+        false => Ok(abstract_.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_from_heading() {
+        let document =
+            Document::from_source("Intro.\n\n# \"Quoted\" -- `code`'s title...\n\nText.").unwrap();
+        assert_eq!(document.title, "“Quoted” – code’s title…");
+    }
 }
@@ -0,0 +1,251 @@
+//! Crate-local module for the attributes of images and for figures.
+//!
+//! A paragraph that consists of a single image is a figure, with the alt text
+//! of the image as its caption, unless it is empty. Other images are inline.
+//! The paragraph is unwrapped beforehand (see [`crate::xref`]), so a figure is
+//! an image that is not inside a leaf block.
+//!
+//! The size of an image is set with the `width` and `height` attributes,
+//! either as a percentage of the text width or height, as a length with a unit
+//! like `3cm`, or as a number of pixels. The `placement` attribute of a figure
+//! is passed to the LaTeX `figure` environment, e.g. `h` or `tbp`.
+
+#[cfg(any(feature = "html", feature = "html-wasm", feature = "latex"))]
+use jotdown::Attributes;
+use jotdown::Container;
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use jotdown::{AttributeValue, Event};
+use log::warn;
+
+/// The units that lengths can be given in, besides percentages and pixels.
+const UNITS: &[&str] = &["cm", "mm", "in", "pt", "pc", "bp", "em", "ex"];
+
+/// The attributes of an image that are not written as HTML attributes.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+const ATTRIBUTES: &[&str] = &["width", "height", "placement"];
+
+/// Returns whether the events inside `container` are inline.
+pub(crate) fn is_leaf(container: &Container) -> bool {
+    container.is_block() && !container.is_block_container()
+}
+
+/// A length given in an attribute.
+#[derive(Debug, PartialEq)]
+enum Length<'a> {
+    /// A percentage of the text width or height.
+    Relative(f64),
+    Pixels(f64),
+    Absolute(f64, &'a str),
+}
+
+impl<'a> Length<'a> {
+    fn parse(value: &'a str) -> Option<Self> {
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let length = match (number.parse().ok(), unit) {
+            (Some(n), "%") => Some(Length::Relative(n)),
+            (Some(n), "" | "px") => Some(Length::Pixels(n)),
+            (Some(n), unit) if UNITS.contains(&unit) => Some(Length::Absolute(n, unit)),
+            _ => None,
+        };
+        if length.is_none() {
+            warn!("Invalid image size `{value}`");
+        }
+        length
+    }
+}
+
+/// Converts a size attribute to a LaTeX length, where percentages are
+/// relative to `reference`, e.g. `\textwidth`.
+#[cfg(feature = "latex")]
+pub(crate) fn latex_length(value: &str, reference: &str) -> Option<String> {
+    Some(match Length::parse(value)? {
+        Length::Relative(n) => format!("{}{reference}", n / 100.0),
+        // CSS pixels are 1/96 in, and big points 1/72 in
+        Length::Pixels(n) => format!("{}bp", n * 0.75),
+        Length::Absolute(n, unit) => format!("{n}{unit}"),
+    })
+}
+
+/// Converts a size attribute to a CSS length.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn css_length(value: &str) -> Option<String> {
+    Some(match Length::parse(value)? {
+        Length::Relative(n) => format!("{n}%"),
+        Length::Pixels(n) => format!("{n}px"),
+        // Big points are what CSS calls points
+        Length::Absolute(n, "bp") => format!("{n}pt"),
+        Length::Absolute(n, unit) => format!("{n}{unit}"),
+    })
+}
+
+/// Returns the `\includegraphics` options for an image with the given
+/// attributes.
+#[cfg(feature = "latex")]
+pub(crate) fn latex_options(attrs: &Attributes, figure: bool) -> String {
+    let width = attrs
+        .get("width")
+        .and_then(|w| latex_length(&w.to_string(), r"\textwidth"));
+    let height = attrs
+        .get("height")
+        .and_then(|h| latex_length(&h.to_string(), r"\textheight"));
+    match (width, height) {
+        (Some(width), Some(height)) => format!("[width={width},height={height}]"),
+        (Some(width), None) => format!("[width={width}]"),
+        (None, Some(height)) => format!("[height={height}]"),
+        (None, None) if figure => r"[width=\textwidth]".to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+/// Moves the size attributes of an image into its `style` attribute, and
+/// removes the ones that only apply to LaTeX.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn html_attributes<'s>(attrs: &Attributes<'s>) -> Attributes<'s> {
+    let mut style: Vec<_> = ["width", "height"]
+        .into_iter()
+        .filter_map(|key| {
+            let length = css_length(&attrs.get(key)?.to_string())?;
+            Some(format!("{key}: {length}"))
+        })
+        .collect();
+    if let Some(s) = attrs.get("style") {
+        style.push(s.to_string());
+    }
+
+    let mut html = Attributes::new();
+    for (key, value) in attrs.iter() {
+        if !ATTRIBUTES.contains(&key) && key != "style" {
+            html.insert(key, value.clone());
+        }
+    }
+    if !style.is_empty() {
+        html.insert("style", AttributeValue::from(style.join("; ")));
+    }
+    html
+}
+
+/// Returns an iterator adapter that sets the size of images and writes
+/// figures as `<figure>` elements, with their alt text as the caption.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+pub(crate) fn html<'s>(events: impl Iterator<Item = Event<'s>>) -> impl Iterator<Item = Event<'s>> {
+    let mut leaf = 0;
+    // The figure being read, which is buffered up to its end
+    let mut figure: Option<Vec<Event<'s>>> = None;
+    events.flat_map(move |event| {
+        if let Some(events) = figure.as_mut() {
+            events.push(event);
+            if !matches!(events.last(), Some(Event::End(Container::Image(..)))) {
+                return Vec::new();
+            }
+            return write_figure(figure.take().unwrap());
+        }
+
+        match event {
+            Event::Start(Container::Image(dest, link_type), attrs) => {
+                let image =
+                    Event::Start(Container::Image(dest, link_type), html_attributes(&attrs));
+                if leaf > 0 {
+                    return vec![image];
+                }
+                figure = Some(vec![image]);
+                Vec::new()
+            }
+            Event::Start(ref c, _) if is_leaf(c) => {
+                leaf += 1;
+                vec![event]
+            }
+            Event::End(ref c) if is_leaf(c) => {
+                leaf -= 1;
+                vec![event]
+            }
+            event => vec![event],
+        }
+    })
+}
+
+/// Writes the events of an image as a figure.
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn write_figure(image: Vec<Event>) -> Vec<Event> {
+    let caption = image[1..image.len() - 1].to_vec();
+    let mut events = raw_block("<figure>\n".into());
+    events.extend(image);
+    if !caption.is_empty() {
+        events.extend(raw_block("<figcaption>".into()));
+        events.extend(caption);
+        events.extend(raw_block("</figcaption>".into()));
+    }
+    events.extend(raw_block("</figure>".into()));
+    events
+}
+
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn raw_block<'s>(html: String) -> Vec<Event<'s>> {
+    vec![
+        Event::Start(Container::RawBlock { format: "html" }, Attributes::new()),
+        Event::Str(html.into()),
+        Event::End(Container::RawBlock { format: "html" }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    use jotdown::{html::Renderer, Parser, Render};
+
+    use super::*;
+
+    #[cfg(feature = "latex")]
+    #[test]
+    fn test_latex_lengths() {
+        assert_eq!(
+            latex_length("50%", r"\textwidth").as_deref(),
+            Some(r"0.5\textwidth")
+        );
+        assert_eq!(latex_length("3cm", "").as_deref(), Some("3cm"));
+        assert_eq!(latex_length("200", "").as_deref(), Some("150bp"));
+        assert_eq!(latex_length("wide", ""), None);
+    }
+
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    #[test]
+    fn test_css_lengths() {
+        assert_eq!(css_length("200").as_deref(), Some("200px"));
+        assert_eq!(css_length("12.5%").as_deref(), Some("12.5%"));
+    }
+
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    #[test]
+    fn test_html() {
+        // The paragraph is unwrapped by the cross-references beforehand
+        let events = Parser::new(r#"![A *plot*](a.png){width="50%" placement=h}"#).filter(|e| {
+            !matches!(
+                e,
+                Event::Start(Container::Paragraph, _) | Event::End(Container::Paragraph)
+            )
+        });
+        let mut s = String::new();
+        Renderer::default().push(html(events), &mut s).unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                "<figure>\n",
+                r#"<img style="width: 50%" alt="A plot" src="a.png">"#,
+                "\n<figcaption>A <strong>plot</strong>\n</figcaption>\n",
+                "</figure>\n",
+            )
+        );
+
+        let mut s = String::new();
+        Renderer::default()
+            .push(html(Parser::new("An ![icon](i.png){height=1em}.")), &mut s)
+            .unwrap();
+        assert_eq!(
+            s,
+            "<p>An <img style=\"height: 1em\" alt=\"icon\" src=\"i.png\">.</p>\n"
+        );
+    }
+}
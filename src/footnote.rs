@@ -0,0 +1,375 @@
+//! Crate-local module for numbering footnotes across all texts of a document.
+//!
+//! Footnote labels are local to the text they are defined in, though a
+//! reference to a label that is not defined in the same text falls back to a
+//! definition in another text. Footnotes are numbered in the order they are
+//! first referenced. Unused definitions and references to undefined labels
+//! produce a warning.
+//!
+//! In LaTeX output, the footnote is written with `\footnote` at its first
+//! reference and referenced with `\footref` after that. In HTML output, the
+//! references are replaced by numbered links, and [`Footnotes::endnotes`]
+//! returns the list of footnotes to put at the end of the document.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use jotdown::{Attributes, Container, Event};
+use log::warn;
+
+use crate::{bib::Bibliography, xref::Backend, ParsedDocument};
+
+/// A footnote definition, identified by the index of its text and its label.
+type Key<'s> = (usize, &'s str);
+
+pub(crate) struct Footnotes<'s> {
+    /// The footnote definitions of each text, keyed by their label.
+    definitions: Vec<HashMap<&'s str, Vec<Event<'s>>>>,
+    /// The definition each reference of each text points to, in order.
+    references: Vec<Vec<Option<Key<'s>>>>,
+    /// The numbers of the referenced footnotes.
+    numbers: HashMap<Key<'s>, usize>,
+    /// The first reference to each footnote, as the index of the text and the
+    /// index of the reference within the text.
+    firsts: HashSet<(usize, usize)>,
+    /// The referenced footnotes in the order of their numbers.
+    order: Vec<Key<'s>>,
+}
+
+impl<'s> Footnotes<'s> {
+    /// Collects the footnotes of a document. The definitions are read with
+    /// their citations already replaced, so `bibliography` must be the one
+    /// the texts are rendered with.
+    pub(crate) fn new(parsed: &ParsedDocument<'s>, bibliography: &Bibliography) -> Self {
+        let mut definitions = Vec::new();
+        let mut labels = Vec::new();
+        for i in 0..parsed.len() {
+            let mut defs = HashMap::new();
+            let mut refs = Vec::new();
+            let mut events = bibliography.cite(i, parsed.events(i));
+            while let Some(event) = events.next() {
+                match event {
+                    Event::FootnoteReference(label) => refs.push(label),
+                    Event::Start(Container::Footnote { label }, _) => {
+                        defs.insert(label, definition(&mut events));
+                    }
+                    _ => {}
+                }
+            }
+            definitions.push(defs);
+            labels.push(refs);
+        }
+
+        let mut footnotes = Self {
+            definitions,
+            references: Vec::new(),
+            numbers: HashMap::new(),
+            firsts: HashSet::new(),
+            order: Vec::new(),
+        };
+        for (i, refs) in labels.into_iter().enumerate() {
+            let refs = refs
+                .into_iter()
+                .enumerate()
+                .map(|(n, label)| {
+                    let key = footnotes.find(i, label);
+                    match key {
+                        Some(key) if !footnotes.numbers.contains_key(&key) => {
+                            footnotes.order.push(key);
+                            footnotes.numbers.insert(key, footnotes.order.len());
+                            footnotes.firsts.insert((i, n));
+                        }
+                        Some(_) => {}
+                        None => warn!("Reference to undefined footnote `{label}`"),
+                    }
+                    key
+                })
+                .collect();
+            footnotes.references.push(refs);
+        }
+
+        for (i, defs) in footnotes.definitions.iter().enumerate() {
+            for label in defs.keys() {
+                if !footnotes.numbers.contains_key(&(i, *label)) {
+                    warn!("Footnote `{label}` is never referenced");
+                }
+            }
+        }
+
+        footnotes
+    }
+
+    /// Finds the definition of `label` referenced from the text at `index`.
+    fn find(&self, index: usize, label: &'s str) -> Option<Key<'s>> {
+        if self.definitions[index].contains_key(label) {
+            return Some((index, label));
+        }
+        self.definitions
+            .iter()
+            .position(|defs| defs.contains_key(label))
+            .map(|i| (i, label))
+    }
+
+    fn definition(&self, (index, label): Key<'s>) -> &[Event<'s>] {
+        &self.definitions[index][label]
+    }
+
+    /// Returns an iterator adapter that replaces the footnote references of
+    /// the text at `index` and removes its footnote definitions.
+    pub(crate) fn resolve<'a, I: Iterator<Item = Event<'s>>>(
+        &'a self,
+        index: usize,
+        events: I,
+        backend: Backend,
+    ) -> Resolve<'a, 's, I> {
+        Resolve {
+            notes: self,
+            inner: events,
+            index,
+            backend,
+            count: 0,
+            buf: VecDeque::new(),
+        }
+    }
+
+    /// Returns the list of footnotes for the end of an HTML document, or
+    /// nothing if no footnotes are referenced. The list is split into its
+    /// items, each with the index of the text the footnote is defined in, so
+    /// that they can be rendered like the rest of that text.
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    pub(crate) fn endnotes(&self) -> Vec<(usize, Vec<Event<'s>>)> {
+        let mut items = Vec::new();
+        for (i, key) in self.order.iter().enumerate() {
+            let number = i + 1;
+            let mut events = raw_block(format!(r#"<li id="fn{number}">"#));
+            let mut definition = self.definition(*key).to_vec();
+            let backlink = raw_inline(
+                format!("<a href=\"#fnref{number}\" role=\"doc-backlink\">\u{21a9}\u{fe0e}</a>"),
+                "html",
+            );
+            match definition
+                .iter()
+                .rposition(|e| !matches!(e, Event::Blankline))
+            {
+                Some(last) if definition[last] == Event::End(Container::Paragraph) => {
+                    definition.splice(last..last, backlink);
+                }
+                _ => {
+                    definition.push(Event::Start(Container::Paragraph, Attributes::new()));
+                    definition.extend(backlink);
+                    definition.push(Event::End(Container::Paragraph));
+                }
+            }
+            events.extend(definition);
+            events.extend(raw_block("</li>".into()));
+            items.push((key.0, events));
+        }
+
+        if let Some((_, first)) = items.first_mut() {
+            first.splice(
+                0..0,
+                raw_block("<section role=\"doc-endnotes\">\n<hr>\n<ol>".into()),
+            );
+        }
+        if let Some((_, last)) = items.last_mut() {
+            last.extend(raw_block("</ol>\n</section>".into()));
+        }
+        items
+    }
+}
+
+/// Collects the events of a footnote definition, up to its end.
+fn definition<'s>(events: &mut impl Iterator<Item = Event<'s>>) -> Vec<Event<'s>> {
+    let mut depth = 0;
+    let mut definition = Vec::new();
+    for event in events {
+        match event {
+            Event::Start(Container::Footnote { .. }, _) => depth += 1,
+            Event::End(Container::Footnote { .. }) if depth == 0 => break,
+            Event::End(Container::Footnote { .. }) => depth -= 1,
+            _ => {}
+        }
+        definition.push(event);
+    }
+    definition
+}
+
+fn raw_inline<'s>(s: String, format: &'s str) -> [Event<'s>; 3] {
+    [
+        Event::Start(Container::RawInline { format }, Attributes::new()),
+        Event::Str(s.into()),
+        Event::End(Container::RawInline { format }),
+    ]
+}
+
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+fn raw_block<'s>(s: String) -> Vec<Event<'s>> {
+    vec![
+        Event::Start(Container::RawBlock { format: "html" }, Attributes::new()),
+        Event::Str(s.into()),
+        Event::End(Container::RawBlock { format: "html" }),
+    ]
+}
+
+/// Iterator adapter that resolves the footnotes of a text.
+pub(crate) struct Resolve<'a, 's, I: Iterator<Item = Event<'s>>> {
+    notes: &'a Footnotes<'s>,
+    inner: I,
+    index: usize,
+    backend: Backend,
+    /// The number of references read so far.
+    count: usize,
+    buf: VecDeque<Event<'s>>,
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Resolve<'a, 's, I> {
+    /// Writes the footnote inline, as the content of a `Footnote` container.
+    /// Paragraphs are joined with `\par`, since they are rendered as text
+    /// separated by blank lines.
+    fn inline_footnote(&mut self, key: Key<'s>, number: usize) {
+        let label = key.1;
+        self.buf.push_back(Event::Start(
+            Container::Footnote { label },
+            Attributes::new(),
+        ));
+        self.buf
+            .extend(raw_inline(format!(r"\label{{fn:{number}}}"), "latex"));
+
+        let mut depth = 0;
+        let mut first = true;
+        for event in self.notes.definition(key) {
+            match event {
+                Event::Start(Container::Paragraph, _) if depth == 0 => {
+                    if !first {
+                        self.buf.extend(raw_inline(r"\par ".into(), "latex"));
+                    }
+                    first = false;
+                }
+                Event::End(Container::Paragraph) if depth == 0 => {}
+                Event::Blankline => {}
+                Event::Start(c, _) if c.is_block() => {
+                    depth += 1;
+                    first = false;
+                    self.buf.push_back(event.clone());
+                }
+                Event::End(c) if c.is_block() => {
+                    depth -= 1;
+                    self.buf.push_back(event.clone());
+                }
+                _ => self.buf.push_back(event.clone()),
+            }
+        }
+        self.buf
+            .push_back(Event::End(Container::Footnote { label }));
+    }
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Iterator for Resolve<'a, 's, I> {
+    type Item = Event<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.buf.pop_front() {
+            return Some(event);
+        }
+
+        match self.inner.next()? {
+            Event::Start(Container::Footnote { .. }, _) => {
+                definition(&mut self.inner);
+                self.next()
+            }
+            Event::FootnoteReference(label) => {
+                let n = self.count;
+                self.count += 1;
+                let Some(key) = self.notes.references[self.index][n] else {
+                    return Some(Event::Str(format!("[^{label}]").into()));
+                };
+                let number = self.notes.numbers[&key];
+                let first = self.notes.firsts.contains(&(self.index, n));
+                match self.backend {
+                    Backend::Latex if first => self.inline_footnote(key, number),
+                    Backend::Latex => self
+                        .buf
+                        .extend(raw_inline(format!(r"\footref{{fn:{number}}}"), "latex")),
+                    Backend::Html => {
+                        let id = match first {
+                            true => format!(r#" id="fnref{number}""#),
+                            false => String::new(),
+                        };
+                        self.buf.extend(raw_inline(
+                            format!(
+                                r##"<a{id} href="#fn{number}" role="doc-noteref"><sup>{number}</sup></a>"##
+                            ),
+                            "html",
+                        ));
+                    }
+                }
+                self.buf.pop_front()
+            }
+            event => Some(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    use jotdown::{html, Render};
+
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    use super::*;
+    use crate::Document;
+
+    fn document(texts: &[&str]) -> Document {
+        let mut document = Document::default();
+        document.texts(texts.iter().copied());
+        document
+    }
+
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    #[test]
+    fn test_html_numbering() {
+        let document = document(&[
+            "A[^a] and b[^b].\n\n[^a]: One.\n\n[^b]: Two.\n\n[^u]: Unused.",
+            "C[^a] and d[^a] and e[^x].\n\n[^a]: Three.",
+        ]);
+        let parsed = document.parse();
+        let bibliography = Bibliography::new(&parsed, None, Default::default(), "en_US").unwrap();
+        let notes = Footnotes::new(&parsed, &bibliography);
+        let mut s = String::new();
+        let events = notes.resolve(1, parsed.events(1), Backend::Html);
+        html::Renderer::default().push(events, &mut s).unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                r##"<p>C<a id="fnref3" href="#fn3" role="doc-noteref"><sup>3</sup></a> and "##,
+                r##"d<a href="#fn3" role="doc-noteref"><sup>3</sup></a> and e[^x].</p>"##,
+                "\n",
+            )
+        );
+
+        let mut s = String::new();
+        html::Renderer::default()
+            .push(notes.endnotes().into_iter().flat_map(|(_, e)| e), &mut s)
+            .unwrap();
+        assert!(s.contains("<li id=\"fn3\">\n<p>Three.<a href=\"#fnref3\" role=\"doc-backlink\">"));
+        assert!(!s.contains("Unused"));
+    }
+
+    #[cfg(feature = "latex")]
+    #[test]
+    fn test_latex() {
+        let document = document(&[
+            "A[^a].\n\n[^a]: One.\n\n    Two.",
+            "B[^a] and c[^b].\n\n[^a]: Three.\n\n[^b]: Four.",
+        ]);
+        let mut bytes = Vec::new();
+        crate::Builder::default()
+            .standalone(false)
+            .write_latex(&document.parse(), &mut bytes)
+            .unwrap();
+        let latex = String::from_utf8(bytes).unwrap();
+        assert!(latex.contains(r"A\footnote{\label{fn:1}One.\par Two.}."));
+        assert!(
+            latex.contains(r"B\footnote{\label{fn:2}Three.} and c\footnote{\label{fn:3}Four.}.")
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Crate-local module for the front matter of Djot files.
+//!
+//! Front matter is a TOML table at the top of a file, either delimited by
+//! `+++` lines or given as a raw block in the `toml` format (` ``` =toml`). It
+//! has the same fields as a document in a manifest, including build options,
+//! but none of them are required. The front matter is removed from the text.
+
+use std::{collections::BTreeMap, io, path::PathBuf};
+
+use serde::Deserialize;
+use toml::value::Datetime;
+
+use crate::{manifest::BuilderManifest, Author, DocumentType};
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct FrontMatter {
+    pub(crate) title: Option<String>,
+    pub(crate) date: Option<Datetime>,
+    #[serde(default, alias = "author")]
+    pub(crate) authors: Vec<Author>,
+    #[serde(default, alias = "bibliographies")]
+    pub(crate) bibliography: Vec<PathBuf>,
+    #[serde(alias = "type")]
+    pub(crate) document_type: Option<DocumentType>,
+    pub(crate) subtitle: Option<String>,
+    #[serde(rename = "abstract")]
+    pub(crate) abstract_: Option<String>,
+    #[serde(default, alias = "keyword")]
+    pub(crate) keywords: Vec<String>,
+    pub(crate) thanks: Option<String>,
+    pub(crate) doi: Option<String>,
+    pub(crate) license: Option<String>,
+    #[serde(default)]
+    pub(crate) metadata: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub(crate) builder: BuilderManifest,
+}
+
+impl FrontMatter {
+    /// Splits the front matter off the top of `text`, returning it along with
+    /// the rest of the text.
+    pub(crate) fn split(text: &str) -> io::Result<(Option<Self>, &str)> {
+        let Some((toml, body)) = split_plus(text).or_else(|| split_raw(text)) else {
+            return Ok((None, text));
+        };
+        let front_matter = toml::from_str(toml).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid front matter: {e}"),
+            )
+        })?;
+        Ok((Some(front_matter), body))
+    }
+}
+
+/// Splits off front matter delimited by `+++` lines.
+fn split_plus(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix("+++")?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "+++" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Splits off front matter given as a `=toml` raw block, which may be
+/// preceded by blank lines.
+fn split_raw(text: &str) -> Option<(&str, &str)> {
+    let start = text.len() - text.trim_start().len();
+    let text = &text[start..];
+    let fence_len = text.find(|c| c != '`').unwrap_or(text.len());
+    if fence_len < 3 {
+        return None;
+    }
+    let (first, rest) = text.split_once('\n')?;
+    if first[fence_len..].trim() != "=toml" {
+        return None;
+    }
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let line_trimmed = line.trim_end();
+        if line_trimmed.len() >= fence_len && line_trimmed.chars().all(|c| c == '`') {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let (front_matter, body) =
+            FrontMatter::split("+++\ntitle = \"A\"\nauthor = [\"B\"]\n+++\n\n# Text\n").unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("A"));
+        assert_eq!(front_matter.authors.len(), 1);
+        assert_eq!(body, "\n# Text\n");
+
+        let (front_matter, body) =
+            FrontMatter::split("\n```` =toml\nnumber-sections = true\n````\nText").unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.title, None);
+        assert_eq!(front_matter.builder.number_sections, Some(true));
+        assert_eq!(body, "Text");
+
+        let text = "``` =latex\n\\maketitle\n```\n";
+        assert_eq!(FrontMatter::split(text).unwrap().1, text);
+        assert!(FrontMatter::split("+++\ntitle = \n+++\n").is_err());
+    }
+}
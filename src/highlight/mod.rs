@@ -0,0 +1,345 @@
+//! Crate-local module for syntax highlighting of code blocks.
+//!
+//! Code blocks are highlighted with the grammars and themes bundled with
+//! syntect, if the `highlight` feature is enabled, and replaced by raw
+//! blocks: `<span>`s classed by their scope in HTML output, styled by the
+//! stylesheet of the [`Theme`], and a `Verbatim` environment from `fvextra`
+//! with `\textcolor` commands in LaTeX output.
+//!
+//! Code blocks with the class `numberLines` get line numbers, and the lines
+//! listed in an `hl` attribute (e.g. `hl="1,3-5"`) are highlighted.
+
+#[cfg(feature = "highlight")]
+mod syntaxes;
+mod themes;
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Write,
+    ops::RangeInclusive,
+    sync::Mutex,
+};
+
+use jotdown::{Attributes, Container, Event};
+use log::warn;
+#[cfg(feature = "highlight")]
+use syntaxes::Syntax;
+pub(crate) use themes::{Theme, DEFAULT_THEME};
+
+use crate::{utils::escape_html, xref::Backend};
+
+/// Languages that are not expected to have a syntax.
+const PLAIN_LANGUAGES: &[&str] = &["", "text", "txt", "plain"];
+
+/// Stand-in for the grammars when the `highlight` feature is disabled, so
+/// that no language has one.
+#[cfg(not(feature = "highlight"))]
+enum Syntax {}
+
+#[cfg(not(feature = "highlight"))]
+impl Syntax {
+    fn find(_: &str) -> Option<Self> {
+        None
+    }
+
+    fn html(&self, _: &str) -> Vec<String> {
+        match *self {}
+    }
+
+    fn latex(&self, _: &str, _: &Theme) -> Vec<String> {
+        match *self {}
+    }
+}
+
+/// Returns an iterator adapter that highlights the code blocks in `events`.
+pub(crate) fn highlight<'s, I: Iterator<Item = Event<'s>>>(
+    events: I,
+    theme: &'static Theme,
+    backend: Backend,
+) -> Highlight<'s, I> {
+    Highlight {
+        inner: events,
+        theme,
+        backend,
+        buf: VecDeque::new(),
+    }
+}
+
+/// Iterator adapter that replaces code blocks by highlighted raw blocks.
+pub(crate) struct Highlight<'s, I: Iterator<Item = Event<'s>>> {
+    inner: I,
+    theme: &'static Theme,
+    backend: Backend,
+    buf: VecDeque<Event<'s>>,
+}
+
+impl<'s, I: Iterator<Item = Event<'s>>> Iterator for Highlight<'s, I> {
+    type Item = Event<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.buf.pop_front() {
+            return Some(event);
+        }
+
+        let (language, attrs) = match self.inner.next()? {
+            Event::Start(Container::CodeBlock { language }, attrs) => (language, attrs),
+            event => return Some(event),
+        };
+
+        let mut code = String::new();
+        for event in self.inner.by_ref() {
+            match event {
+                Event::Str(s) => code.push_str(&s),
+                Event::End(Container::CodeBlock { .. }) => break,
+                _ => {}
+            }
+        }
+
+        let syntax = Syntax::find(language);
+        if syntax.is_none() && !PLAIN_LANGUAGES.contains(&language.to_lowercase().as_str()) {
+            // Warn once per language rather than for every code block
+            static WARNED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+            let mut warned = WARNED.lock().unwrap();
+            if warned
+                .get_or_insert_with(HashSet::new)
+                .insert(language.to_lowercase())
+            {
+                warn!("No syntax highlighting available for `{language}`");
+            }
+        }
+        let options = Options::new(&attrs);
+        if syntax.is_none() && !options.number_lines && options.highlight.is_empty() {
+            self.buf.extend([
+                Event::Str(code.into()),
+                Event::End(Container::CodeBlock { language }),
+            ]);
+            return Some(Event::Start(Container::CodeBlock { language }, attrs));
+        }
+
+        let code = code.strip_suffix('\n').unwrap_or(&code);
+        let (format, out) = match self.backend {
+            Backend::Html => {
+                let lines = match syntax {
+                    Some(ref syntax) => syntax.html(code),
+                    None => code.split('\n').map(escape_html).collect(),
+                };
+                ("html", html(&lines, language, &attrs, &options))
+            }
+            Backend::Latex => {
+                let lines = match syntax {
+                    Some(ref syntax) => syntax.latex(code, self.theme),
+                    None => code.split('\n').map(escape_latex).collect(),
+                };
+                ("latex", latex(&lines, &options))
+            }
+        };
+        self.buf.extend([
+            Event::Str(out.into()),
+            Event::End(Container::RawBlock { format }),
+        ]);
+        Some(Event::Start(
+            Container::RawBlock { format },
+            Attributes::new(),
+        ))
+    }
+}
+
+/// The line numbering options of a code block.
+struct Options {
+    number_lines: bool,
+    highlight: Vec<RangeInclusive<usize>>,
+    /// The line ranges as written in the `hl` attribute.
+    highlight_str: String,
+}
+
+impl Options {
+    fn new(attrs: &Attributes) -> Self {
+        let number_lines = attrs.get("class").is_some_and(|class| {
+            class
+                .to_string()
+                .split_whitespace()
+                .any(|class| class == "numberLines")
+        });
+        let highlight_str = attrs
+            .get("hl")
+            .map(|hl| hl.to_string().replace(' ', ""))
+            .unwrap_or_default();
+        let highlight = match parse_ranges(&highlight_str) {
+            Some(ranges) => ranges,
+            None => {
+                warn!("Invalid line ranges `{highlight_str}` in `hl` attribute");
+                Vec::new()
+            }
+        };
+        Self {
+            number_lines,
+            highlight_str: match highlight.is_empty() {
+                true => String::new(),
+                false => highlight_str,
+            },
+            highlight,
+        }
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlight.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// Parses comma-separated line numbers and ranges like `1,3-5`.
+fn parse_ranges(s: &str) -> Option<Vec<RangeInclusive<usize>>> {
+    s.split(',')
+        .filter(|range| !range.is_empty())
+        .map(|range| match range.split_once('-') {
+            Some((start, end)) => Some(start.parse().ok()?..=end.parse().ok()?),
+            None => range.parse().ok().map(|n| n..=n),
+        })
+        .collect()
+}
+
+fn html(lines: &[String], language: &str, attrs: &Attributes, options: &Options) -> String {
+    let mut out = String::from("<pre");
+    if let Some(id) = attrs.get("id") {
+        write!(out, r#" id="{}""#, escape_html(&id.to_string())).unwrap();
+    }
+    out.push_str(r#" class="highlight"#);
+    if let Some(class) = attrs.get("class") {
+        write!(out, " {}", escape_html(&class.to_string())).unwrap();
+    }
+    out.push_str(r#""><code"#);
+    if !language.is_empty() {
+        write!(out, r#" class="language-{}""#, escape_html(language)).unwrap();
+    }
+    out.push('>');
+
+    for (i, line) in lines.iter().enumerate() {
+        match options.is_highlighted(i + 1) {
+            true => out.push_str(r#"<span class="line hl">"#),
+            false => out.push_str(r#"<span class="line">"#),
+        }
+        out.push_str(line);
+        out.push_str("</span>\n");
+    }
+    out.push_str("</code></pre>");
+    out
+}
+
+fn latex(lines: &[String], options: &Options) -> String {
+    let mut out = String::from(r"\begin{Verbatim}[commandchars=\\\{\}");
+    if options.number_lines {
+        out.push_str(",numbers=left");
+    }
+    if !options.highlight_str.is_empty() {
+        write!(out, ",highlightlines={{{}}}", options.highlight_str).unwrap();
+    }
+    out.push_str("]\n");
+
+    for line in lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str("\\end{Verbatim}\n");
+    out
+}
+
+/// Escapes the command characters of a `Verbatim` environment.
+fn escape_latex(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str(r"\textbackslash{}"),
+            '{' => out.push_str(r"\{"),
+            '}' => out.push_str(r"\}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use jotdown::{html, Parser, Render};
+
+    use super::*;
+
+    fn theme() -> &'static Theme {
+        Theme::find(DEFAULT_THEME).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "highlight")]
+    fn test_html() {
+        let text = "{.numberLines hl=\"2-3\"}\n```python\ndef f(x):\n    \"\"\"a\n    b\"\"\"\n    return x < 1\n```";
+        let mut s = String::new();
+        html::Renderer::default()
+            .push(highlight(Parser::new(text), theme(), Backend::Html), &mut s)
+            .unwrap();
+        let lines: Vec<_> = s.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with(concat!(
+            r#"<pre class="highlight numberLines"><code class="language-python">"#,
+            r#"<span class="line"><span class="hl-source hl-python">"#,
+        )));
+        assert!(lines[0]
+            .contains(r#"<span class="hl-storage hl-type hl-function hl-python">def</span>"#));
+        // The docstring is opened again on the line where it continues
+        assert!(lines[2].starts_with(concat!(
+            r#"<span class="line hl"><span class="hl-source hl-python">"#,
+            r#"<span class="hl-comment hl-block hl-documentation hl-python">    b"#,
+        )));
+        assert!(lines[3].starts_with(r#"<span class="line"><span"#));
+        for line in &lines[..4] {
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count()
+            );
+        }
+        assert_eq!(lines[4], "</code></pre>");
+    }
+
+    #[test]
+    #[cfg(feature = "highlight")]
+    fn test_latex() {
+        let syntax = Syntax::find("latex").unwrap();
+        let lines = syntax.latex("\\emph{a} % b", theme());
+        let options = Options {
+            number_lines: true,
+            highlight: vec![1..=1],
+            highlight_str: "1".into(),
+        };
+        assert_eq!(
+            latex(&lines, &options),
+            concat!(
+                r"\begin{Verbatim}[commandchars=\\\{\},numbers=left,highlightlines={1}]",
+                "\n",
+                r"\textcolor[HTML]{62A35C}{\textbackslash{}emph}\{a\} ",
+                r"\textit{\textcolor[HTML]{969896}{% b}}",
+                "\n\\end{Verbatim}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_plain() {
+        let text = "{.numberLines}\n```\na < b\n```";
+        let mut s = String::new();
+        html::Renderer::default()
+            .push(highlight(Parser::new(text), theme(), Backend::Html), &mut s)
+            .unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                r#"<pre class="highlight numberLines"><code>"#,
+                r#"<span class="line">a &lt; b</span>"#,
+                "\n</code></pre>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(parse_ranges("1,3-5"), Some(vec![1..=1, 3..=5]));
+        assert_eq!(parse_ranges(""), Some(vec![]));
+        assert_eq!(parse_ranges("a-2"), None);
+    }
+}
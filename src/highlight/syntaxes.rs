@@ -0,0 +1,124 @@
+use std::{fmt::Write, sync::OnceLock};
+
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Style},
+    html::{line_tokens_to_classed_spans, ClassStyle},
+    parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet},
+};
+
+use super::{escape_latex, themes::hex, Theme};
+use crate::utils::escape_html;
+
+/// The classes of the `<span>`s in HTML output, which are the atoms of the
+/// scope with a prefix, e.g. `hl-comment hl-line hl-rust`.
+pub(super) const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Returns the grammars bundled with syntect.
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// A language grammar.
+pub(crate) struct Syntax(&'static SyntaxReference);
+
+impl Syntax {
+    /// Finds a bundled grammar by the name of its language or by a file
+    /// extension, ignoring case.
+    pub(crate) fn find(language: &str) -> Option<Self> {
+        if language.is_empty() {
+            return None;
+        }
+        syntax_set().find_syntax_by_token(language).map(Self)
+    }
+
+    /// Returns the lines of `code` as HTML, with the tokens in `<span>`s
+    /// classed by their scope. Every line is self-contained, so scopes that
+    /// span lines are closed at the end of a line and opened again on the
+    /// next.
+    pub(super) fn html(&self, code: &str) -> Vec<String> {
+        let mut state = ParseState::new(self.0);
+        let mut stack = ScopeStack::new();
+        lines(code)
+            .map(|line| {
+                let mut out = String::new();
+                for scope in stack.as_slice() {
+                    write!(out, r#"<span class="{}">"#, classes(*scope)).unwrap();
+                }
+                let open = stack.len() as isize;
+                let tokens = state
+                    .parse_line(&line, syntax_set())
+                    .map_err(|e| e.to_string())
+                    .and_then(|ops| {
+                        line_tokens_to_classed_spans(&line, &ops, CLASS_STYLE, &mut stack)
+                            .map_err(|e| e.to_string())
+                    });
+                let (html, delta) = tokens.unwrap_or_else(|_| (escape_html(&line), 0));
+                out.push_str(&html.replace('\n', ""));
+                for _ in 0..open + delta {
+                    out.push_str("</span>");
+                }
+                out
+            })
+            .collect()
+    }
+
+    /// Returns the lines of `code` as LaTeX, with the tokens colored by
+    /// `\textcolor` commands unless they have the default color of `theme`.
+    pub(super) fn latex(&self, code: &str, theme: &Theme) -> Vec<String> {
+        let mut highlighter = HighlightLines::new(self.0, &theme.inner);
+        let foreground = theme.inner.settings.foreground;
+        lines(code)
+            .map(|line| {
+                let Ok(tokens) = highlighter.highlight_line(&line, syntax_set()) else {
+                    return escape_latex(line.trim_end_matches('\n'));
+                };
+                // Tokens with the same style are colored together
+                let mut merged: Vec<(Style, String)> = Vec::new();
+                for (style, text) in tokens {
+                    match merged.last_mut() {
+                        Some((last, s)) if *last == style => s.push_str(text),
+                        _ => merged.push((style, text.to_string())),
+                    }
+                }
+                let mut out = String::new();
+                for (style, text) in merged {
+                    let text = escape_latex(text.trim_end_matches('\n'));
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let mut s = match Some(style.foreground) == foreground {
+                        true => text,
+                        false => {
+                            format!(r"\textcolor[HTML]{{{}}}{{{text}}}", hex(style.foreground))
+                        }
+                    };
+                    if style.font_style.contains(FontStyle::BOLD) {
+                        s = format!(r"\textbf{{{s}}}");
+                    }
+                    if style.font_style.contains(FontStyle::ITALIC) {
+                        s = format!(r"\textit{{{s}}}");
+                    }
+                    out.push_str(&s);
+                }
+                out
+            })
+            .collect()
+    }
+}
+
+/// Returns the lines of `code` with a line ending, which the grammars expect.
+fn lines(code: &str) -> impl Iterator<Item = String> + '_ {
+    code.split('\n').map(|line| format!("{line}\n"))
+}
+
+/// Returns the classes of the `<span>` for a scope, like syntect does.
+fn classes(scope: Scope) -> String {
+    scope
+        .build_string()
+        .split('.')
+        .map(|atom| format!("hl-{atom}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,116 @@
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+#[cfg(feature = "highlight")]
+use syntect::highlighting::{Color, ThemeSet};
+#[cfg(all(feature = "highlight", any(feature = "html", feature = "html-wasm")))]
+use syntect::html::css_for_theme_with_class_style;
+
+/// A color theme for highlighted code.
+pub(crate) struct Theme {
+    /// The name of the theme, normalized by [`normalize`].
+    name: String,
+    #[cfg(feature = "highlight")]
+    pub(crate) inner: syntect::highlighting::Theme,
+    /// The background of highlighted lines.
+    pub(crate) highlight: String,
+}
+
+pub(crate) const DEFAULT_THEME: &str = "inspired-github";
+
+/// The background of highlighted lines for themes that do not set one.
+const DEFAULT_LINE_HIGHLIGHT: &str = "FFF8C5";
+
+/// Returns the themes bundled with syntect.
+fn themes() -> &'static [Theme] {
+    static THEMES: OnceLock<Vec<Theme>> = OnceLock::new();
+    THEMES.get_or_init(|| {
+        #[cfg(feature = "highlight")]
+        let themes = ThemeSet::load_defaults()
+            .themes
+            .into_iter()
+            .map(|(name, inner)| Theme {
+                name: normalize(&name),
+                highlight: inner
+                    .settings
+                    .line_highlight
+                    .map_or_else(|| DEFAULT_LINE_HIGHLIGHT.to_string(), hex),
+                inner,
+            })
+            .collect();
+        #[cfg(not(feature = "highlight"))]
+        let themes = vec![Theme {
+            name: normalize(DEFAULT_THEME),
+            highlight: DEFAULT_LINE_HIGHLIGHT.to_string(),
+        }];
+        themes
+    })
+}
+
+/// Normalizes the name of a theme, so that e.g. `Solarized (light)` can be
+/// given as `solarized-light`.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Returns a color as hexadecimal RGB.
+#[cfg(feature = "highlight")]
+pub(super) fn hex(color: Color) -> String {
+    format!("{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+}
+
+impl Theme {
+    /// Finds a bundled theme by name.
+    pub(crate) fn find(name: &str) -> Option<&'static Self> {
+        let name = normalize(name);
+        themes().iter().find(|theme| theme.name == name)
+    }
+
+    /// Returns the stylesheet for highlighted code blocks in HTML output.
+    #[cfg(any(feature = "html", feature = "html-wasm"))]
+    pub(crate) fn css(&self) -> String {
+        let mut css = String::new();
+        #[cfg(feature = "highlight")]
+        {
+            let settings = &self.inner.settings;
+            css.push_str(".highlight {\n");
+            if let Some(background) = settings.background {
+                writeln!(css, "  background: #{};", hex(background)).unwrap();
+            }
+            if let Some(foreground) = settings.foreground {
+                writeln!(css, "  color: #{};", hex(foreground)).unwrap();
+            }
+            css.push_str("}\n");
+            let classes = css_for_theme_with_class_style(&self.inner, super::syntaxes::CLASS_STYLE);
+            css.push_str(&classes.unwrap_or_default());
+        }
+        write!(
+            css,
+            ".highlight .line.hl {{\n  display: inline-block;\n  width: 100%;\n  background: #{};\n}}\n",
+            self.highlight
+        )
+        .unwrap();
+        css.push_str(LINE_NUMBERS_CSS);
+        css
+    }
+}
+
+#[cfg(any(feature = "html", feature = "html-wasm"))]
+const LINE_NUMBERS_CSS: &str = r#".numberLines code {
+  counter-reset: line;
+}
+.numberLines .line::before {
+  counter-increment: line;
+  content: counter(line);
+  display: inline-block;
+  width: 2em;
+  margin-right: 1em;
+  text-align: right;
+  opacity: .5;
+  user-select: none;
+}
+"#;
@@ -6,18 +6,37 @@
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
+    fs,
     io::{self, Write},
+    path::{Path, PathBuf},
 };
 
 use jotdown::{html, Container, Event, Parser, Render};
 use rayon::prelude::*;
 
 use super::Builder;
-use crate::Document;
+use crate::{
+    admonition,
+    asset::Assets,
+    author::{self, Author},
+    bib::BibError,
+    figure,
+    highlight::{highlight, Theme},
+    quotes::Quotes,
+    symbols::Symbols,
+    template::{self, Variables},
+    utils::escape_html,
+    xref::{Backend, CrossRefs, Entry},
+    ParsedDocument, Resource,
+};
 
 const MAIN_CSS: &[u8] = include_bytes!("main.css");
 const KATEX_CSS: &[u8] = include_bytes!("katex.css");
 
+/// The template that HTML output is written with unless another one is given
+/// with [`Builder::html_template`].
+pub const DEFAULT_TEMPLATE: &str = include_str!("template.html");
+
 impl Builder {
     /// Build the document as HTML and write it to the given writer.
     ///
@@ -29,79 +48,215 @@ impl Builder {
     /// let builder = Builder::default();
     /// let document = Document::from("Hello, world!".to_string());
     /// let mut bytes = Vec::new();
-    /// builder.write_html(&document, &mut bytes).unwrap();
+    /// builder.write_html(&document.parse(), &mut bytes).unwrap();
     /// ```
     pub fn write_html<W: Write + Send>(
         &self,
-        document: &Document,
+        parsed: &ParsedDocument,
         mut w: W,
     ) -> Result<(), HtmlError> {
+        let document = parsed.document();
         let mut inner = || -> Result<(), HtmlError> {
-            if self.standalone {
-                writeln!(w, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>")?;
-                writeln!(w, "<style>")?;
-                w.write_all(MAIN_CSS)?;
-                writeln!(w, "</style>")?;
-                w.write_all(KATEX_CSS)?;
-
-                writeln!(w, "</head>")?;
-                writeln!(w, "<body>")?;
-            }
+            let analysis = parsed.analysis(self, Backend::Html)?;
+            let (bibliography, footnotes, crossrefs) = (
+                &analysis.bibliography,
+                &analysis.footnotes,
+                &analysis.crossrefs,
+            );
+            let theme = self.theme();
+            let symbols = Symbols::new(&self.symbols);
+            let quotes = Quotes::new(&self.locale);
+            let mut assets = Assets::new(parsed);
+            let root = self.output_dir.as_deref().unwrap_or(Path::new(""));
+            assets.html(self.asset_mode, root, &self.asset_dir, self.srcset)?;
 
+            let mut title_block = Vec::new();
             if self.add_title {
+                let w = &mut title_block;
                 writeln!(w, "<header>")?;
-                writeln!(w, "<h1>{}</h1>", document.title)?;
-                if !document.authors.is_empty() {
-                    write!(w, "<p>")?;
-                    for (i, author) in document.authors.iter().enumerate() {
-                        match i {
-                            0 => (),
-                            i if i == document.authors.len() - 1 => write!(w, " and ")?,
-                            _ => write!(w, ", ")?,
-                        }
-                        write!(w, "{}", author)?;
-                    }
-                    writeln!(w, "</p>")?;
+                writeln!(w, "<h1>{}</h1>", escape_html(&document.title))?;
+                if let Some(ref subtitle) = document.subtitle {
+                    writeln!(w, r#"<p class="subtitle">{}</p>"#, escape_html(subtitle))?;
+                }
+                write_authors(&document.authors, &mut *w)?;
+                let djot = |src: &str, w: &mut Vec<u8>| -> io::Result<()> {
+                    let events = quotes.replace(Parser::new(src), Backend::Html);
+                    html::Renderer::default().write(events, w)
+                };
+                if let Some(ref thanks) = document.thanks {
+                    writeln!(w, r#"<div class="thanks">"#)?;
+                    djot(thanks, w)?;
+                    writeln!(w, "</div>")?;
+                }
+                if let Some(ref abstract_) = document.abstract_ {
+                    writeln!(w, r#"<section class="abstract" role="doc-abstract">"#)?;
+                    writeln!(w, "<h2>Abstract</h2>")?;
+                    djot(abstract_, w)?;
+                    writeln!(w, "</section>")?;
+                }
+                if !document.keywords.is_empty() {
+                    let keywords = escape_html(&document.keywords.join(", "));
+                    writeln!(
+                        w,
+                        r#"<p class="keywords"><strong>Keywords:</strong> {keywords}</p>"#
+                    )?;
+                }
+                if let Some(ref doi) = document.doi {
+                    let url = escape_html(&format!("https://doi.org/{doi}"));
+                    writeln!(w, r#"<p class="doi"><a href="{url}">{url}</a></p>"#)?;
+                }
+                if let Some(ref license) = document.license {
+                    let license = escape_html(license);
+                    writeln!(w, r#"<p class="license">License: {license}</p>"#)?;
                 }
                 writeln!(w, "</header>")?;
             }
 
-            document
-                .texts
-                .par_iter()
-                .try_fold_with(Vec::new(), |mut buf, text| {
-                    let mut opts = katex::Opts::builder()
-                        .throw_on_error(false)
-                        .build()
-                        .unwrap();
-                    let mut in_math = false;
-                    let events = Parser::new(text).map(|event| match event {
-                        Event::Start(Container::Math { display }, attrs) => {
-                            opts.set_display_mode(display);
-                            in_math = true;
-                            Event::Start(Container::RawBlock { format: "html" }, attrs)
-                        }
-                        Event::End(Container::Math { .. }) => {
-                            in_math = false;
-                            Event::End(Container::RawBlock { format: "html" })
-                        }
-                        Event::Str(s) if in_math => {
-                            Event::Str(katex::render_with_opts(&s, &opts).unwrap().into())
-                        }
-                        _ => event,
-                    });
-
-                    html::Renderer::default().write(events, &mut buf)?;
+            let mut outline = Vec::new();
+            if self.toc {
+                let entries: Vec<_> = crossrefs
+                    .contents
+                    .iter()
+                    .filter(|entry| entry.level <= self.toc_depth)
+                    .collect();
+                write_outline(&entries, "toc", "Contents", &mut outline)?;
+            }
+            if self.lof {
+                let entries: Vec<_> = crossrefs.figures.iter().collect();
+                write_outline(&entries, "lof", "List of Figures", &mut outline)?;
+            }
+            if self.lot {
+                let entries: Vec<_> = crossrefs.tables.iter().collect();
+                write_outline(&entries, "lot", "List of Tables", &mut outline)?;
+            }
+
+            let chain = Chain {
+                crossrefs,
+                symbols: &symbols,
+                quotes,
+                assets: &assets,
+                theme,
+            };
+            let mut body = (0..parsed.len())
+                .into_par_iter()
+                .try_fold_with(Vec::new(), |mut buf, i| {
+                    let events = bibliography.cite(i, parsed.events(i));
+                    let events = footnotes.resolve(i, events, Backend::Html);
+                    chain.write(i, events, &mut buf)?;
                     Ok(buf)
                 })
                 .collect::<Result<Vec<Vec<u8>>, HtmlError>>()?
-                .into_iter()
-                .try_for_each(|s| w.write_all(&s))?;
+                .concat();
 
-            if self.standalone {
-                writeln!(w, "</body>\n</html>")?;
+            if !bibliography.references.is_empty() {
+                html::Renderer::default()
+                    .write(bibliography.references.iter().cloned(), &mut body)?;
             }
 
+            for (i, events) in footnotes.endnotes() {
+                chain.write(i, events.into_iter(), &mut body)?;
+            }
+
+            if !self.standalone {
+                w.write_all(&title_block)?;
+                w.write_all(&outline)?;
+                w.write_all(&body)?;
+                return Ok(());
+            }
+
+            let mut styles = Vec::new();
+            if self.default_css {
+                writeln!(styles, "<style>")?;
+                styles.extend_from_slice(MAIN_CSS);
+                styles.extend_from_slice(theme.css().as_bytes());
+                writeln!(styles, "</style>")?;
+                styles.extend_from_slice(KATEX_CSS);
+            }
+            for stylesheet in &self.stylesheets {
+                match stylesheet {
+                    Resource::Link(href) => {
+                        let href = escape_html(href);
+                        writeln!(styles, r#"<link rel="stylesheet" href="{href}">"#)?;
+                    }
+                    Resource::Inline(path) => {
+                        writeln!(styles, "<style>")?;
+                        styles.extend_from_slice(fs::read_to_string(path)?.trim_end().as_bytes());
+                        writeln!(styles, "\n</style>")?;
+                    }
+                }
+            }
+            let mut scripts = Vec::new();
+            for script in &self.scripts {
+                match script {
+                    Resource::Link(src) => {
+                        let src = escape_html(src);
+                        writeln!(scripts, r#"<script src="{src}"></script>"#)?;
+                    }
+                    Resource::Inline(path) => {
+                        writeln!(scripts, "<script>")?;
+                        scripts.extend_from_slice(fs::read_to_string(path)?.trim_end().as_bytes());
+                        writeln!(scripts, "\n</script>")?;
+                    }
+                }
+            }
+
+            let names: Vec<_> = document.authors.iter().map(|a| a.name.as_str()).collect();
+            let mut abstract_ = Vec::new();
+            if let Some(ref src) = document.abstract_ {
+                let events = quotes.replace(Parser::new(src), Backend::Html);
+                html::Renderer::default().write(events, &mut abstract_)?;
+            }
+
+            // Metadata comes first, so that it cannot replace the variables
+            // that are filled in here
+            let mut variables: Variables = document
+                .metadata
+                .iter()
+                .map(|(name, value)| (name.clone(), escape_html(value)))
+                .collect();
+            variables.extend(
+                [
+                    ("lang", self.locale.replace('_', "-")),
+                    ("locale", self.locale.clone()),
+                    ("title", escape_html(&document.title)),
+                    (
+                        "subtitle",
+                        escape_html(document.subtitle.as_deref().unwrap_or("")),
+                    ),
+                    ("authors", escape_html(&names.join(", "))),
+                    (
+                        "date",
+                        document
+                            .date
+                            .format_with_locale(&self.locale)
+                            .map_or_else(String::new, |date| escape_html(&date)),
+                    ),
+                    ("abstract", template::value(abstract_)),
+                    ("keywords", escape_html(&document.keywords.join(", "))),
+                    ("doi", escape_html(document.doi.as_deref().unwrap_or(""))),
+                    (
+                        "license",
+                        escape_html(document.license.as_deref().unwrap_or("")),
+                    ),
+                    ("styles", template::value(styles)),
+                    ("scripts", template::value(scripts)),
+                    ("title-block", template::value(title_block)),
+                    ("outline", template::value(outline)),
+                    ("body", template::value(body)),
+                ]
+                .map(|(name, value)| (name.to_string(), value)),
+            );
+
+            let (template, path) = match self.html_template {
+                Some(ref path) => (fs::read_to_string(path)?, Some(path.clone())),
+                None => (DEFAULT_TEMPLATE.to_string(), None),
+            };
+            let html = template::render(&template, &variables).map_err(|message| HtmlError {
+                document_name: None,
+                kind: HtmlErrorKind::Template { path, message },
+            })?;
+            w.write_all(html.as_bytes())?;
+
             Ok(())
         };
 
@@ -109,6 +264,167 @@ impl Builder {
     }
 }
 
+/// Writes a `<nav>` with a nested list of links to the entries, which are
+/// nested by the levels of headings.
+fn write_outline<W: Write>(
+    entries: &[&Entry],
+    class: &str,
+    title: &str,
+    mut w: W,
+) -> io::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let role = match class {
+        "toc" => r#" role="doc-toc""#,
+        _ => "",
+    };
+    writeln!(w, r#"<nav class="{class}"{role}>"#)?;
+    writeln!(w, "<h2>{title}</h2>")?;
+
+    // The levels of the open lists
+    let mut levels: Vec<u16> = Vec::new();
+    for entry in entries {
+        while levels.last().is_some_and(|&level| level > entry.level) {
+            writeln!(w, "</li>\n</ol>")?;
+            levels.pop();
+        }
+        match levels.last() {
+            Some(&level) if level == entry.level => writeln!(w, "</li>")?,
+            _ => {
+                writeln!(w, "<ol>")?;
+                levels.push(entry.level);
+            }
+        }
+
+        write!(w, r##"<li><a href="#{}">"##, escape_html(&entry.id))?;
+        if let Some(ref number) = entry.number {
+            write!(w, r#"<span class="section-number">{number}</span> "#)?;
+        }
+        write!(w, "{}</a>", escape_html(&entry.title))?;
+    }
+    for _ in levels {
+        writeln!(w, "</li>\n</ol>")?;
+    }
+    writeln!(w, "</nav>")
+}
+
+/// The adapters that the events of a text are run through once its citations
+/// and footnotes are resolved.
+struct Chain<'a> {
+    crossrefs: &'a CrossRefs,
+    symbols: &'a Symbols<'a>,
+    quotes: Quotes,
+    assets: &'a Assets,
+    theme: &'static Theme,
+}
+
+impl Chain<'_> {
+    /// Writes the events of the text at `index` as HTML, with math rendered
+    /// by KaTeX.
+    fn write<'s>(
+        &self,
+        index: usize,
+        events: impl Iterator<Item = Event<'s>>,
+        w: &mut Vec<u8>,
+    ) -> Result<(), HtmlError> {
+        let mut opts = katex::Opts::builder()
+            .throw_on_error(false)
+            .build()
+            .unwrap();
+        let mut in_math = false;
+        let mut math_id = None;
+        let events = self.crossrefs.resolve(index, events, Backend::Html);
+        let events = self.symbols.replace(events, Backend::Html);
+        let events = self.quotes.replace(events, Backend::Html);
+        let events = self.assets.rewrite(index, events);
+        let events = highlight(events, self.theme, Backend::Html);
+        let events = figure::html(admonition::html(events)).map(|event| match event {
+            Event::Start(Container::Math { display }, attrs) => {
+                opts.set_display_mode(display);
+                in_math = true;
+                // The id is lost on the raw block, so keep it as an anchor
+                math_id = attrs.get("id").map(ToString::to_string);
+                Event::Start(Container::RawBlock { format: "html" }, attrs)
+            }
+            Event::End(Container::Math { .. }) => {
+                in_math = false;
+                Event::End(Container::RawBlock { format: "html" })
+            }
+            Event::Str(s) if in_math => {
+                let mut html = katex::render_with_opts(&s, &opts).unwrap();
+                if let Some(id) = math_id.take() {
+                    html.insert_str(0, &format!(r#"<span id="{id}"></span>"#));
+                }
+                Event::Str(html.into())
+            }
+            _ => event,
+        });
+        html::Renderer::default().write(events, w)?;
+        Ok(())
+    }
+}
+/// Writes the authors, with links to their numbered affiliations, their
+/// ORCID iDs and their email addresses.
+fn write_authors<W: Write>(authors: &[Author], mut w: W) -> io::Result<()> {
+    if authors.is_empty() {
+        return Ok(());
+    }
+    let affiliations = author::affiliations(authors);
+
+    write!(w, r#"<p class="authors">"#)?;
+    for (i, author) in authors.iter().enumerate() {
+        match i {
+            0 => (),
+            i if i == authors.len() - 1 => write!(w, " and ")?,
+            _ => write!(w, ", ")?,
+        }
+        let name = escape_html(&author.name);
+        write!(w, r#"<span class="author">"#)?;
+        match author.email {
+            Some(ref email) => {
+                let email = escape_html(email);
+                write!(w, r#"<a class="name" href="mailto:{email}">{name}</a>"#)?;
+            }
+            None => write!(w, r#"<span class="name">{name}</span>"#)?,
+        }
+        if let Some(n) = author.affiliation_number(&affiliations) {
+            write!(w, r##"<sup><a href="#affiliation-{n}">{n}</a></sup>"##)?;
+        }
+        if author.corresponding {
+            write!(
+                w,
+                r#"<sup class="corresponding" title="Corresponding author">*</sup>"#
+            )?;
+        }
+        if let Some(ref orcid) = author.orcid {
+            let orcid = escape_html(orcid);
+            write!(
+                w,
+                r#" <a class="orcid" href="https://orcid.org/{orcid}" title="ORCID {orcid}">iD</a>"#
+            )?;
+        }
+        write!(w, "</span>")?;
+    }
+    writeln!(w, "</p>")?;
+
+    if !affiliations.is_empty() {
+        writeln!(w, r#"<ol class="affiliations">"#)?;
+        for (i, affiliation) in affiliations.iter().enumerate() {
+            let affiliation = escape_html(affiliation);
+            writeln!(w, r#"<li id="affiliation-{}">{affiliation}</li>"#, i + 1)?;
+        }
+        writeln!(w, "</ol>")?;
+    }
+    if authors.iter().any(|a| a.corresponding) {
+        writeln!(
+            w,
+            r#"<p class="corresponding"><sup>*</sup>Corresponding author</p>"#
+        )?;
+    }
+    Ok(())
+}
+
 /// An error that can occur when rendering HTML.
 #[non_exhaustive]
 #[derive(Debug)]
@@ -148,6 +464,15 @@ impl From<katex::Error> for HtmlError {
     }
 }
 
+impl From<BibError> for HtmlError {
+    fn from(e: BibError) -> Self {
+        Self {
+            document_name: None,
+            kind: HtmlErrorKind::Bib(e),
+        }
+    }
+}
+
 impl Display for HtmlError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if let Some(name) = &self.document_name {
@@ -156,6 +481,17 @@ impl Display for HtmlError {
         match &self.kind {
             HtmlErrorKind::Io(e) => write!(f, "io error: {e}"),
             HtmlErrorKind::Katex(_) => write!(f, "failed to render math with katex"),
+            HtmlErrorKind::Bib(e) => write!(f, "bibliography error: {e}"),
+            HtmlErrorKind::Template {
+                path: Some(path),
+                message,
+            } => write!(f, "invalid template {}: {message}", path.display()),
+            HtmlErrorKind::Template {
+                path: None,
+                message,
+            } => {
+                write!(f, "invalid template: {message}")
+            }
         }
     }
 }
@@ -165,6 +501,8 @@ impl Error for HtmlError {
         match &self.kind {
             HtmlErrorKind::Io(source) => Some(source),
             HtmlErrorKind::Katex(source) => Some(source),
+            HtmlErrorKind::Bib(source) => Some(source),
+            HtmlErrorKind::Template { .. } => None,
         }
     }
 }
@@ -177,4 +515,84 @@ pub enum HtmlErrorKind {
     Io(io::Error),
     /// An error that occurred while rendering math with [`katex`].
     Katex(katex::Error),
+    /// An error that occurred while loading the bibliography.
+    Bib(BibError),
+    /// The template is malformed. `path` is `None` for the default template.
+    Template {
+        path: Option<PathBuf>,
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Document;
+
+    #[test]
+    fn test_template() {
+        let mut document = Document::from("Some text.");
+        document.title("A & B").metadata("site", "<Home>");
+        let parsed = document.parse();
+
+        let mut html = Vec::new();
+        Builder::default().write_html(&parsed, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"en-US\">\n<head>\n"));
+        assert!(html.contains("<title>A &amp; B</title>\n<style>\n"));
+        assert!(html.ends_with("<body>\n<p>Some text.</p>\n</body>\n</html>\n"));
+
+        let dir = std::env::temp_dir().join("djoc-test-html-template");
+        fs::create_dir_all(&dir).unwrap();
+        let (template, script) = (dir.join("template.html"), dir.join("script.js"));
+        fs::write(&template, "$title$ | $site$\n$styles$\n$scripts$\n$body$\n").unwrap();
+        fs::write(&script, "run();\n").unwrap();
+        let mut html = Vec::new();
+        Builder::default()
+            .html_template(&template)
+            .default_css(false)
+            .stylesheet(Resource::Link("style.css".into()))
+            .script(Resource::Inline(script))
+            .write_html(&parsed, &mut html)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            "A &amp; B | &lt;Home&gt;\n<link rel=\"stylesheet\" href=\"style.css\">\n\
+             <script>\nrun();\n</script>\n<p>Some text.</p>\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_title_block() {
+        let mut document = Document::from("Text.");
+        document.title("A & <B>");
+        let mut html = Vec::new();
+        Builder::default()
+            .standalone(false)
+            .add_title(true)
+            .write_html(&document.parse(), &mut html)
+            .unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<header>\n<h1>A &amp; &lt;B&gt;</h1>\n"));
+    }
+
+    #[test]
+    fn test_endnotes() {
+        let document = Document::from(
+            "{#sec}\n# Intro\n\nA[^a].\n\n[^a]: See [](#sec) and $`x^2`, \"quoted\".",
+        );
+        let mut html = Vec::new();
+        Builder::default()
+            .standalone(false)
+            .locale("de_DE")
+            .write_html(&document.parse(), &mut html)
+            .unwrap();
+        let html = String::from_utf8(html).unwrap();
+        let endnotes = &html[html.find("<section role=\"doc-endnotes\">").unwrap()..];
+        assert!(endnotes.contains(r##"See <a href="#sec">Intro</a> and "##));
+        assert!(endnotes.contains(r#"<span class="katex">"#));
+        assert!(!endnotes.contains(r#"<span class="math inline">"#));
+        assert!(endnotes.contains("\u{201e}quoted\u{201c}"));
+    }
 }
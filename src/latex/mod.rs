@@ -3,15 +3,28 @@ mod renderer;
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
+    fs,
     io::{self, Write},
+    path::PathBuf,
 };
 
 use jotdown::{Parser, Render};
 use rayon::prelude::*;
-use renderer::Renderer;
+pub(crate) use renderer::{escape, escape_url, Renderer};
 
 use super::Builder;
-use crate::{latex, Document};
+use crate::{
+    asset::Assets,
+    author::{self, Author},
+    bib::BibError,
+    highlight::highlight,
+    latex,
+    quotes::Quotes,
+    symbols::{Symbols, EMOJI_FONT_COMMAND},
+    template::{self, Variables},
+    xref::Backend,
+    Document, DocumentType, ParsedDocument,
+};
 
 impl Builder {
     /// Build the document as LaTeX and write it to the given writer.
@@ -24,69 +37,217 @@ impl Builder {
     /// let mut builder = Builder::default();
     /// let document = Document::from("Hello, world!".to_string());
     /// builder
-    ///     .write_latex(&document, &mut std::io::stdout())
+    ///     .write_latex(&document.parse(), &mut std::io::stdout())
     ///     .unwrap();
     /// ```
-    pub fn write_latex<W: Write>(&self, document: &Document, mut w: W) -> Result<(), LatexError> {
+    pub fn write_latex<W: Write>(&self, parsed: &ParsedDocument, w: W) -> Result<(), LatexError> {
+        let mut assets = Assets::new(parsed);
+        // LaTeX cannot include SVG images, so they are converted even without
+        // a build directory to cache them in
+        let cache_dir = match self.build_dir {
+            Some(ref build_dir) => build_dir.clone(),
+            None => std::env::temp_dir().join("djoc"),
+        };
+        assets
+            .convert_svgs(&cache_dir)
+            .map_err(|e| e.document_name(&parsed.document().title))?;
+        self.write_latex_with_assets(parsed, &assets, w)
+    }
+
+    /// Builds the document as LaTeX, referring to images by the paths of
+    /// `assets`.
+    pub(crate) fn write_latex_with_assets<W: Write>(
+        &self,
+        parsed: &ParsedDocument,
+        assets: &Assets,
+        mut w: W,
+    ) -> Result<(), LatexError> {
+        let document = parsed.document();
         let mut inner = || -> Result<(), LatexError> {
-            if self.standalone {
-                writeln!(w, r"\documentclass{{{}}}", document.document_type.as_ref())?;
-
-                DEFAULT_PACKAGES
-                    .iter()
-                    .try_for_each(|package| writeln!(w, r"\usepackage{{{package}}}"))?;
-                w.write_all(DEFAULT_PREAMBLE)?;
-
-                let lang = self
-                    .locale
-                    .split_once('_')
-                    .map_or(self.locale.as_str(), |(s, _)| s);
-                writeln!(w, r"\setdefaultlanguage{{{lang}}}")?;
-
-                write!(w, r"\title{{")?;
-                latex::Renderer::default().write(Parser::new(&document.title), &mut w)?;
-                writeln!(w, "}}")?;
-
-                match document.date.format_with_locale(&self.locale) {
-                    Some(date) => writeln!(w, r"\date{{{date}}}")?,
-                    None => writeln!(w, r"\predate{{}}\date{{}}\postdate{{}}")?,
-                }
+            let analysis = parsed.analysis(self, Backend::Latex)?;
+            let (bibliography, footnotes, crossrefs) = (
+                &analysis.bibliography,
+                &analysis.footnotes,
+                &analysis.crossrefs,
+            );
+            let division = self.division(document);
+            let theme = self.theme();
+            let symbols = Symbols::new(&self.symbols);
+            let quotes = Quotes::new(&self.locale);
+
+            let mut title_block = Vec::new();
+            if self.add_title {
+                writeln!(title_block, r"\maketitle")?;
+                write_title_block(document, quotes, &mut title_block)?;
+            }
+            let mut outline = Vec::new();
+            if self.toc {
+                writeln!(outline, r"\tableofcontents")?;
+            }
+            if self.lof {
+                writeln!(outline, r"\listoffigures")?;
+            }
+            if self.lot {
+                writeln!(outline, r"\listoftables")?;
+            }
+
+            let mut body = (0..parsed.len())
+                .into_par_iter()
+                .try_fold_with(Vec::new(), |mut buf, i| {
+                    let events = bibliography.cite(i, parsed.events(i));
+                    let events = footnotes.resolve(i, events, Backend::Latex);
+                    let events = crossrefs.resolve(i, events, Backend::Latex);
+                    let events = symbols.replace(events, Backend::Latex);
+                    let events = quotes.replace(events, Backend::Latex);
+                    let events = assets.rewrite(i, events);
+                    latex::Renderer::default()
+                        .number_sections(self.number_sections || self.toc)
+                        .top_level_division(division)
+                        .write(highlight(events, theme, Backend::Latex), &mut buf)?;
+                    Ok(buf)
+                })
+                .collect::<Result<Vec<Vec<u8>>, LatexError>>()?
+                .concat();
+            if !bibliography.references.is_empty() {
+                latex::Renderer::default()
+                    .write(bibliography.references.iter().cloned(), &mut body)?;
+            }
 
-                if document.authors.is_empty() {
-                    writeln!(w, r"\preauthor{{}}\author{{}}\postauthor{{}}")?;
+            if !self.standalone {
+                w.write_all(&title_block)?;
+                w.write_all(&outline)?;
+                w.write_all(&body)?;
+                return Ok(());
+            }
+
+            let mut preamble = Vec::new();
+            preamble.extend_from_slice(DEFAULT_PREAMBLE);
+            writeln!(
+                preamble,
+                r"\definecolor{{djochighlight}}{{HTML}}{{{}}}",
+                theme.highlight
+            )?;
+            writeln!(preamble, r"\fvset{{highlightcolor=djochighlight}}")?;
+            if symbols.has_emoji(parsed) {
+                let font = self.emoji_font.as_deref().unwrap_or(DEFAULT_EMOJI_FONT);
+                writeln!(preamble, r"\newfontfamily{EMOJI_FONT_COMMAND}{{{font}}}")?;
+            }
+            if self.number_sections {
+                // Number down to \subparagraph, like in HTML output
+                writeln!(preamble, r"\setcounter{{secnumdepth}}{{5}}")?;
+            } else if self.toc {
+                // Unnumbered headings are only listed in the table of
+                // contents if they are not starred, so leave out the
+                // numbers instead
+                writeln!(preamble, r"\setcounter{{secnumdepth}}{{-2}}")?;
+            }
+            if document.document_type != DocumentType::Article {
+                // Number figures, tables and equations throughout the
+                // document rather than per chapter, like in HTML output
+                for counter in ["figure", "table", "equation"] {
+                    writeln!(preamble, r"\counterwithout{{{counter}}}{{chapter}}")?;
                 }
+            }
+            if self.toc {
+                let depth = division.depth(self.toc_depth, "");
+                writeln!(preamble, r"\setcounter{{tocdepth}}{{{depth}}}")?;
+            }
 
-                for author in &document.authors {
-                    write!(w, r"\author{{{}", author.name)?;
-                    if let Some(ref email) = author.email {
-                        write!(w, r" \thanks{{\href{{mailto:{email}}}{{{email}}}}}")?;
+            let lang = self
+                .locale
+                .split_once('_')
+                .map_or(self.locale.as_str(), |(s, _)| s);
+            writeln!(preamble, r"\setdefaultlanguage{{{lang}}}")?;
+
+            if self.biblatex && !bibliography.files.is_empty() {
+                // Tectonic only ships with bibtex, so use it instead of biber
+                write!(preamble, r"\usepackage[backend=bibtex")?;
+                if let Some(ref style) = self.biblatex_style {
+                    write!(preamble, ",style={style}")?;
+                }
+                writeln!(preamble, "]{{biblatex}}")?;
+                for file in &bibliography.files {
+                    if let Some(name) = file.file_name() {
+                        writeln!(preamble, r"\addbibresource{{{}}}", name.to_string_lossy())?;
                     }
-                    writeln!(w, "}}")?;
                 }
+            }
 
-                writeln!(w, r"\begin{{document}}")?;
+            if document.abstract_.is_some() && document.document_type == DocumentType::Book {
+                // The book class has no abstract environment
+                preamble.extend_from_slice(BOOK_ABSTRACT);
+            }
+            if !document.keywords.is_empty() {
+                let keywords = escape(&document.keywords.join(", "));
+                writeln!(preamble, r"\hypersetup{{pdfkeywords={{{keywords}}}}}")?;
             }
 
-            if self.add_title {
-                writeln!(w, r"\maketitle")?;
+            let djot = |src: &str| -> io::Result<String> {
+                let events = quotes.replace(Parser::new(src), Backend::Latex);
+                let mut buf = Vec::new();
+                latex::Renderer::default().write(events, &mut buf)?;
+                Ok(String::from_utf8_lossy(&buf).trim().to_string())
+            };
+            let mut authors = Vec::new();
+            write_authors(&document.authors, &mut authors)?;
+            let mut abstract_ = Vec::new();
+            if let Some(ref src) = document.abstract_ {
+                let events = quotes.replace(Parser::new(src), Backend::Latex);
+                latex::Renderer::default().write(events, &mut abstract_)?;
             }
+            let packages: String = DEFAULT_PACKAGES
+                .iter()
+                .map(|package| format!("\\usepackage{{{package}}}\n"))
+                .collect();
 
-            document
-                .texts
-                .par_iter()
-                .try_fold_with(Vec::new(), |mut buf, text| {
-                    latex::Renderer::default()
-                        .number_sections(self.number_sections)
-                        .write(Parser::new(text), &mut buf)?;
-                    Ok(buf)
-                })
-                .collect::<Result<Vec<Vec<u8>>, LatexError>>()?
-                .into_iter()
-                .try_for_each(|s| w.write_all(&s))?;
+            // Metadata comes first, so that it cannot replace the variables
+            // that are filled in here
+            let mut variables: Variables = document
+                .metadata
+                .iter()
+                .map(|(name, value)| (name.clone(), escape(value)))
+                .collect();
+            variables.extend(
+                [
+                    ("documentclass", document.document_type.as_ref().into()),
+                    ("packages", template::value(packages.into_bytes())),
+                    ("preamble", template::value(preamble)),
+                    ("title", djot(&document.title)?),
+                    (
+                        "subtitle",
+                        djot(document.subtitle.as_deref().unwrap_or(""))?,
+                    ),
+                    ("thanks", djot(document.thanks.as_deref().unwrap_or(""))?),
+                    (
+                        "date",
+                        document
+                            .date
+                            .format_with_locale(&self.locale)
+                            .unwrap_or_default(),
+                    ),
+                    ("authors", template::value(authors)),
+                    ("abstract", template::value(abstract_)),
+                    ("keywords", escape(&document.keywords.join(", "))),
+                    ("doi", escape(document.doi.as_deref().unwrap_or(""))),
+                    ("license", escape(document.license.as_deref().unwrap_or(""))),
+                    ("lang", lang.into()),
+                    ("locale", self.locale.clone()),
+                    ("title-block", template::value(title_block)),
+                    ("outline", template::value(outline)),
+                    ("body", template::value(body)),
+                ]
+                .map(|(name, value)| (name.to_string(), value)),
+            );
 
-            if self.standalone {
-                writeln!(w, r"\end{{document}}")?;
-            }
+            let (template, path) = match self.latex_template {
+                Some(ref path) => (fs::read_to_string(path)?, Some(path.clone())),
+                None => (DEFAULT_TEMPLATE.to_string(), None),
+            };
+            let latex = template::render(&template, &variables).map_err(|message| LatexError {
+                document_name: None,
+                kind: LatexErrorKind::Template { path, message },
+            })?;
+            w.write_all(latex.as_bytes())?;
 
             Ok(())
         };
@@ -101,7 +262,8 @@ impl Builder {
 pub struct LatexError {
     /// The title of the document that caused the error.
     pub document_name: Option<String>,
-    source: io::Error,
+    /// The kind of error that occurred.
+    pub kind: LatexErrorKind,
 }
 
 impl LatexError {
@@ -118,30 +280,157 @@ impl LatexError {
 impl Display for LatexError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match &self.document_name {
-            Some(document_name) => write!(f, "error writing LaTeX for document {}", document_name),
-            None => write!(f, "error writing LaTeX"),
+            Some(document_name) => write!(f, "error writing LaTeX for document {}", document_name)?,
+            None => write!(f, "error writing LaTeX")?,
+        }
+        match &self.kind {
+            LatexErrorKind::Template {
+                path: Some(path),
+                message,
+            } => write!(f, ": invalid template {}: {message}", path.display()),
+            LatexErrorKind::Template {
+                path: None,
+                message,
+            } => {
+                write!(f, ": invalid template: {message}")
+            }
+            _ => Ok(()),
         }
     }
 }
 
 impl From<io::Error> for LatexError {
-    fn from(source: io::Error) -> Self {
+    fn from(e: io::Error) -> Self {
+        Self {
+            document_name: None,
+            kind: LatexErrorKind::Io(e),
+        }
+    }
+}
+
+impl From<BibError> for LatexError {
+    fn from(e: BibError) -> Self {
         Self {
             document_name: None,
-            source,
+            kind: LatexErrorKind::Bib(e),
         }
     }
 }
 
 impl Error for LatexError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.source)
+        match &self.kind {
+            LatexErrorKind::Io(source) => Some(source),
+            LatexErrorKind::Bib(source) => Some(source),
+            LatexErrorKind::Svg { source, .. } => Some(source),
+            LatexErrorKind::Template { .. } => None,
+        }
     }
 }
 
 unsafe impl Sync for LatexError {}
 
-const DEFAULT_PACKAGES: [&str; 18] = [
+/// The kind of error that can occur when building LaTeX.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum LatexErrorKind {
+    /// An error that occurred while writing to the writer.
+    Io(io::Error),
+    /// An error that occurred while loading the bibliography.
+    Bib(BibError),
+    /// An error that occurred while converting an SVG image to PDF.
+    Svg {
+        path: PathBuf,
+        source: svg2pdf::usvg::Error,
+    },
+    /// The template is malformed. `path` is `None` for the default template.
+    Template {
+        path: Option<PathBuf>,
+        message: String,
+    },
+}
+
+/// Writes the authors with `authblk`, where affiliations are numbered and
+/// shared between authors.
+fn write_authors<W: Write>(authors: &[Author], mut w: W) -> io::Result<()> {
+    let affiliations = author::affiliations(authors);
+    for author in authors {
+        let mut marks: Vec<String> = author
+            .affiliation_number(&affiliations)
+            .map(|n| n.to_string())
+            .into_iter()
+            .collect();
+        if author.corresponding {
+            marks.push("*".into());
+        }
+        match marks.is_empty() {
+            true => write!(w, r"\author{{")?,
+            false => write!(w, r"\author[{}]{{", marks.join(","))?,
+        }
+        write!(w, "{}", escape(&author.name))?;
+        if let Some(ref orcid) = author.orcid {
+            let url = escape_url(&format!("https://orcid.org/{orcid}"));
+            write!(w, r"\,\textsuperscript{{\href{{{url}}}{{iD}}}}")?;
+        }
+        let email = author
+            .email
+            .as_ref()
+            .map(|email| (escape_url(email), escape(email)));
+        match (email, author.corresponding) {
+            (Some((url, email)), true) => write!(
+                w,
+                r"\thanks{{Corresponding author: \href{{mailto:{url}}}{{{email}}}}}"
+            )?,
+            (Some((url, email)), false) => {
+                write!(w, r"\thanks{{\href{{mailto:{url}}}{{{email}}}}}")?
+            }
+            (None, true) => write!(w, r"\thanks{{Corresponding author}}")?,
+            (None, false) => {}
+        }
+        writeln!(w, "}}")?;
+    }
+    for (i, affiliation) in affiliations.iter().enumerate() {
+        writeln!(w, r"\affil[{}]{{{}}}", i + 1, escape(affiliation))?;
+    }
+    Ok(())
+}
+
+/// Writes the abstract, the keywords, the DOI and the license of the document
+/// below the title.
+fn write_title_block<W: Write>(document: &Document, quotes: Quotes, mut w: W) -> io::Result<()> {
+    if let Some(ref abstract_) = document.abstract_ {
+        writeln!(w, r"\begin{{abstract}}")?;
+        let events = quotes.replace(Parser::new(abstract_), Backend::Latex);
+        Renderer::default().write(events, &mut w)?;
+        writeln!(w, r"\end{{abstract}}")?;
+    }
+    if !document.keywords.is_empty() {
+        let keywords = escape(&document.keywords.join(", "));
+        writeln!(w, r"\noindent\textbf{{Keywords:}} {keywords}\par")?;
+    }
+    if let Some(ref doi) = document.doi {
+        let url = escape_url(&format!("https://doi.org/{doi}"));
+        writeln!(w, r"\noindent\textbf{{DOI:}} \url{{{url}}}\par")?;
+    }
+    if let Some(ref license) = document.license {
+        writeln!(w, r"\noindent\textbf{{License:}} {}\par", escape(license))?;
+    }
+    Ok(())
+}
+
+/// The template that LaTeX output is written with unless another one is
+/// given with [`Builder::latex_template`].
+pub const DEFAULT_TEMPLATE: &str = include_str!("template.tex");
+
+const DEFAULT_EMOJI_FONT: &str = "Noto Emoji";
+
+const BOOK_ABSTRACT: &[u8] = br"\providecommand{\abstractname}{Abstract}
+\newenvironment{abstract}
+  {\begin{center}\bfseries\abstractname\end{center}\begin{quotation}}
+  {\end{quotation}}
+";
+
+const DEFAULT_PACKAGES: [&str; 24] = [
     "amsmath",
     "authblk",
     "bookmark",
@@ -157,9 +446,15 @@ const DEFAULT_PACKAGES: [&str; 18] = [
     "lmodern",
     "unicode-math",
     "polyglossia",
+    "csquotes",
     "pifont",
     "enumitem",
     "subcaption",
+    "array",
+    "booktabs",
+    "longtable",
+    "fvextra",
+    "tcolorbox",
 ];
 
 const DEFAULT_PREAMBLE: &[u8] = br#"
@@ -172,6 +467,11 @@ const DEFAULT_PREAMBLE: &[u8] = br#"
 \newlist{tasklist}{itemize}{2}
 \setlist[tasklist]{label=\checkbox}
 
+% Admonitions, with the color and the title as arguments
+\tcbuselibrary{breakable,skins}
+\newtcolorbox{djocadmonition}[2]{breakable, enhanced, colback=#1!5!white,
+  colframe=#1!75!black, fonttitle=\bfseries, title={#2}}
+
 % Other settings
 \UseMicrotypeSet[protrusion]{basicmath} % disable protrusion for tt fonts
 \setlength{\emergencystretch}{3em} % prevent overfull lines
@@ -185,3 +485,114 @@ const DEFAULT_PREAMBLE: &[u8] = br#"
   linktocpage,
   pdfcreator={djoc}}
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let mut document = Document::from("Some text.");
+        document
+            .title("Title")
+            .document_type("report")
+            .doi("10.1000/a_b")
+            .metadata("institute", "R&D")
+            .metadata("title", "Not the title");
+        let parsed = document.parse();
+
+        let mut latex = Vec::new();
+        Builder::default().write_latex(&parsed, &mut latex).unwrap();
+        let latex = String::from_utf8(latex).unwrap();
+        assert!(latex.starts_with("\\documentclass{report}\n\\usepackage{"));
+        assert!(latex.contains("\\counterwithout{figure}{chapter}\n"));
+        assert!(latex.contains("\\title{Title}\n\\predate{}\\date{}\\postdate{}\n"));
+        assert!(latex.ends_with("\\begin{document}\n\nSome text.\n\\end{document}\n"));
+
+        let dir = std::env::temp_dir().join("djoc-test-latex-template");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("template.tex");
+        fs::write(
+            &path,
+            "$title$ at $institute$ costs $$5\n$if(date)$\n$date$\n$endif$\n$doi$\n$body$",
+        )
+        .unwrap();
+        let mut latex = Vec::new();
+        Builder::default()
+            .latex_template(&path)
+            .write_latex(&parsed, &mut latex)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(latex).unwrap(),
+            "Title at R\\&D costs $5\n10.1000/a\\_b\n\nSome text."
+        );
+
+        fs::write(&path, "$if(title)$").unwrap();
+        let error = Builder::default()
+            .latex_template(&path)
+            .write_latex(&parsed, Vec::new())
+            .unwrap_err();
+        assert!(matches!(error.kind, LatexErrorKind::Template { .. }));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_svg() {
+        let dir = std::env::temp_dir().join("djoc-test-latex-svg");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.svg"),
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"/>"#,
+        )
+        .unwrap();
+        let mut document = Document::from("![](a.svg)");
+        document.paths = vec![Some(dir.join("main.dj"))];
+
+        let mut latex = Vec::new();
+        Builder::default()
+            .standalone(false)
+            .write_latex(&document.parse(), &mut latex)
+            .unwrap();
+        let latex = String::from_utf8(latex).unwrap();
+        assert!(latex.contains(".pdf}"));
+        assert!(!latex.contains("a.svg"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_title_block() {
+        let mut document = Document::from("Text.");
+        document.doi("10.1000/a%b#c");
+        let mut latex = Vec::new();
+        Builder::default()
+            .standalone(false)
+            .add_title(true)
+            .write_latex(&document.parse(), &mut latex)
+            .unwrap();
+        let latex = String::from_utf8(latex).unwrap();
+        assert!(latex.contains(r"\url{https://doi.org/10.1000/a\%b\#c}"));
+    }
+
+    #[test]
+    fn test_authors() {
+        let author = Author {
+            name: "Jane Doe".into(),
+            email: Some("jane_doe#1%x@example.com".into()),
+            affiliation: None,
+            orcid: Some("0000-0002-1825-009X#%".into()),
+            corresponding: true,
+        };
+        let mut latex = Vec::new();
+        write_authors(&[author], &mut latex).unwrap();
+        assert_eq!(
+            String::from_utf8(latex).unwrap(),
+            concat!(
+                r"\author[*]{Jane Doe",
+                r"\,\textsuperscript{\href{https://orcid.org/0000-0002-1825-009X\#\%}{iD}}",
+                r"\thanks{Corresponding author: ",
+                r"\href{mailto:jane_doe\#1\%x@example.com}{jane\_doe\#1\%x@example.com}}}",
+                "\n",
+            )
+        );
+    }
+}
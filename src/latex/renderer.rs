@@ -5,7 +5,11 @@
 
 use std::fmt;
 
-use jotdown::{Container, Event, ListKind, OrderedListNumbering, OrderedListStyle, Render};
+use jotdown::{
+    Alignment, Container, Event, ListKind, OrderedListNumbering, OrderedListStyle, Render,
+};
+
+use crate::{admonition, figure, has_class, TopLevelDivision};
 
 #[derive(Default)]
 enum Emit {
@@ -18,6 +22,7 @@ enum Emit {
 #[derive(Default)]
 pub struct Renderer {
     pub number_sections: bool,
+    pub top_level_division: TopLevelDivision,
 }
 
 impl Render for Renderer {
@@ -49,30 +54,186 @@ impl Renderer {
             ..self
         }
     }
+
+    #[must_use]
+    pub fn top_level_division(self, top_level_division: TopLevelDivision) -> Self {
+        Self {
+            top_level_division,
+            ..self
+        }
+    }
 }
 
 #[derive(Default)]
-struct Writer<'s> {
+struct Writer {
     pub number_sections: bool,
+    top_level_division: TopLevelDivision,
+    /// The classes of the current section, which apply to its heading.
+    section_classes: String,
+    /// Whether each open div is an admonition.
+    divs: Vec<bool>,
     emit: Emit,
     first_line: bool,
-    fnrefs: Vec<&'s str>,
+    /// The number of leaf blocks being written, in which images are inline
+    /// rather than figures.
+    leaf: usize,
+    /// The figure being written, which is buffered until its end since it
+    /// only has a caption if the alt text of its image is not empty.
+    figure: Option<Figure>,
+    /// Whether the display math being written is a labelled equation.
+    equation: bool,
+    /// The table being written, which is buffered until its end since the
+    /// column specification must be known up front.
+    table: Option<Table>,
+}
+
+/// The sectioning commands of LaTeX, from `\part` (depth -1) to
+/// `\subparagraph` (depth 5).
+const SECTIONING: [&str; 7] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+/// Line length (in characters) above which table columns get a fixed width,
+/// so that their content is wrapped.
+const TABLE_WRAP_WIDTH: usize = 80;
+
+#[derive(Default)]
+struct Table {
+    label: Option<String>,
+    caption: Option<String>,
+    columns: Vec<Alignment>,
+    /// The longest content of each column, in characters.
+    widths: Vec<usize>,
+    head: String,
+    body: String,
+    in_caption: bool,
+    in_head: bool,
+    cell: usize,
+    cell_start: usize,
+}
+
+impl Table {
+    fn buf(&mut self) -> &mut String {
+        if self.in_caption {
+            self.caption.get_or_insert_with(String::new)
+        } else if self.in_head {
+            &mut self.head
+        } else {
+            &mut self.body
+        }
+    }
+
+    /// Writes the table as a `longtable`, so that it can break across pages.
+    fn write<W: fmt::Write>(&self, mut out: W) -> fmt::Result {
+        let total: usize = self.widths.iter().sum::<usize>() + 3 * self.widths.len();
+        out.write_str(r"\begin{longtable}[]{@{}")?;
+        for (alignment, width) in self.columns.iter().zip(&self.widths) {
+            if total > TABLE_WRAP_WIDTH {
+                let align = match alignment {
+                    Alignment::Center => r"\centering",
+                    Alignment::Right => r"\raggedleft",
+                    Alignment::Left | Alignment::Unspecified => r"\raggedright",
+                };
+                let width = 0.95 * (width + 3) as f64 / total as f64;
+                write!(out, r">{{{align}\arraybackslash}}p{{{width:.2}\linewidth}}")?;
+            } else {
+                out.write_char(match alignment {
+                    Alignment::Center => 'c',
+                    Alignment::Right => 'r',
+                    Alignment::Left | Alignment::Unspecified => 'l',
+                })?;
+            }
+        }
+        writeln!(out, "@{{}}}}")?;
+
+        if let Some(ref caption) = self.caption {
+            write!(out, r"\caption{{{caption}}}")?;
+            if let Some(ref label) = self.label {
+                write!(out, r"\label{{{label}}}")?;
+            }
+            writeln!(out, r"\tabularnewline")?;
+        }
+
+        // The head is repeated on every page
+        writeln!(out, r"\toprule")?;
+        if !self.head.is_empty() {
+            out.write_str(&self.head)?;
+            writeln!(out, r"\midrule")?;
+            writeln!(out, r"\endfirsthead")?;
+            writeln!(out, r"\toprule")?;
+            out.write_str(&self.head)?;
+            writeln!(out, r"\midrule")?;
+        }
+        writeln!(out, r"\endhead")?;
+        writeln!(out, r"\bottomrule")?;
+        writeln!(out, r"\endlastfoot")?;
+        out.write_str(&self.body)?;
+        writeln!(out, r"\end{{longtable}}")
+    }
+}
+
+#[derive(Default)]
+struct Figure {
+    placement: Option<String>,
+    graphics: String,
+    label: Option<String>,
+    caption: String,
+}
+
+impl Figure {
+    fn write<W: fmt::Write>(&self, mut out: W) -> fmt::Result {
+        out.write_str(r"\begin{figure}")?;
+        if let Some(ref placement) = self.placement {
+            write!(out, "[{placement}]")?;
+        }
+        writeln!(out)?;
+        writeln!(out, r"\centering")?;
+        out.write_str(&self.graphics)?;
+        if !self.caption.is_empty() {
+            write!(out, r"\caption{{{}}}", self.caption)?;
+            if let Some(ref label) = self.label {
+                write!(out, r"\label{{{label}}}")?;
+            }
+        }
+        out.write_str("\n\\end{figure}\n")
+    }
 }
 
-impl From<&Renderer> for Writer<'_> {
+impl From<&Renderer> for Writer {
     fn from(r: &Renderer) -> Self {
         Self {
             number_sections: r.number_sections,
+            top_level_division: r.top_level_division,
             ..Self::default()
         }
     }
 }
 
-impl<'s> Writer<'s> {
-    fn render_event<W>(&mut self, e: &Event<'s>, mut out: W) -> fmt::Result
+impl Writer {
+    fn render_event<W>(&mut self, e: &Event, mut out: W) -> fmt::Result
     where
         W: fmt::Write,
     {
+        // Table cells and captions are leaf blocks too, so this comes first
+        match e {
+            Event::Start(c, _) if figure::is_leaf(c) => self.leaf += 1,
+            Event::End(c) if figure::is_leaf(c) => self.leaf -= 1,
+            _ => {}
+        }
+
+        if self.table.is_some() {
+            return self.render_table_event(e, out);
+        }
+        if self.figure.is_some() {
+            return self.render_figure_event(e, out);
+        }
+
         match e {
             Event::Str(s) => match self.emit {
                 Emit::Escaped => write_escaped(&mut out, s)?,
@@ -83,7 +244,8 @@ impl<'s> Writer<'s> {
             Event::LeftSingleQuote => out.write_str("`")?,
             Event::RightSingleQuote => out.write_char('\'')?,
             Event::LeftDoubleQuote => out.write_str("``")?,
-            Event::RightDoubleQuote => out.write_char('"')?,
+            // Not `"`, which is a shorthand in some languages, e.g. German
+            Event::RightDoubleQuote => out.write_str("''")?,
             Event::Ellipsis => out.write_str(r"\ldots")?,
             Event::EnDash => out.write_str(r"\textendash{}")?,
             Event::EmDash => out.write_str(r"\textemdash{}")?,
@@ -94,24 +256,39 @@ impl<'s> Writer<'s> {
             Event::ThematicBreak(_attrs) => {
                 out.write_str("\n\\begin{center}\\rule{0.5\\linewidth}{0.5pt}\\end{center}")?
             }
-            Event::FootnoteReference(label) => {
-                if !self.fnrefs.contains(label) {
-                    self.fnrefs.push(label);
-                }
-                let number = self.fnrefs.iter().position(|l| l == label).unwrap();
-                write!(out, r"\footnotemark[{}]", number)?
-            }
-            Event::Start(c, _attrs) => {
+            // Footnotes are inlined at their references beforehand
+            Event::FootnoteReference(_) => {}
+            Event::Start(c, attrs) => {
                 if self.first_line {
                     self.first_line = false;
-                } else if c.is_block() && !matches!(c, Container::Section { .. }) {
+                } else if c.is_block()
+                    && !matches!(c, Container::Section { .. } | Container::Footnote { .. })
+                {
                     out.write_char('\n')?;
                 }
                 match c {
-                    Container::Paragraph
-                    | Container::Section { .. }
-                    | Container::DescriptionDetails
-                    | Container::Div { .. } => {}
+                    Container::Table => {
+                        self.table = Some(Table {
+                            label: attrs.get("id").map(ToString::to_string),
+                            ..Table::default()
+                        })
+                    }
+                    Container::Section { .. } => {
+                        self.section_classes = attrs
+                            .get("class")
+                            .map(ToString::to_string)
+                            .unwrap_or_default();
+                    }
+                    Container::Div { class } => {
+                        let kind = admonition::Kind::of_div(class, attrs);
+                        self.divs.push(kind.is_some());
+                        if let Some(kind) = kind {
+                            write!(out, r"\begin{{djocadmonition}}{{{}}}{{", kind.color())?;
+                            write_escaped(&mut out, &kind.title(attrs))?;
+                            out.write_str("}\n")?;
+                        }
+                    }
+                    Container::Paragraph | Container::DescriptionDetails => {}
                     Container::Blockquote => out.write_str(r"\begin{quote}")?,
                     Container::DescriptionList => writeln!(out, r"\begin{{description}}")?,
                     Container::Span => out.write_char('{')?,
@@ -122,32 +299,46 @@ impl<'s> Writer<'s> {
                     Container::Insert => out.write_str(r"\ul{")?,
                     Container::Delete => out.write_str(r"\st{")?,
                     Container::Mark => out.write_str(r"\hl{")?,
-                    Container::Link(dest, _) => write!(out, r"\href{{{}}}{{", dest)?,
+                    Container::Link(dest, _) => match dest.strip_prefix('#') {
+                        Some(id) => write!(out, r"\hyperref[{id}]{{")?,
+                        None => write!(out, r"\href{{{}}}{{", dest)?,
+                    },
                     Container::DescriptionTerm => write!(out, r"\item[")?,
-                    Container::Footnote { label } => {
-                        let number = self.fnrefs.iter().position(|l| l == label).unwrap();
-                        write!(out, r"\footnotetext[{}]{{", number)?
+                    Container::Footnote { .. } => out.write_str(r"\footnote{")?,
+                    Container::Image(dest, _) if self.leaf > 0 => {
+                        let options = figure::latex_options(attrs, false);
+                        write!(out, r"\includegraphics{options}{{{dest}}}")?;
+                        // The alt text is not shown
+                        self.emit = Emit::None;
                     }
                     Container::Image(dest, _) => {
-                        writeln!(out, r"\begin{{figure}}")?;
-                        writeln!(out, r"\centering")?;
-                        write!(out, r"\includegraphics[width=\textwidth]{{{dest}}}")?;
-                        out.write_str(r"\caption{")?;
+                        let options = figure::latex_options(attrs, true);
+                        self.figure = Some(Figure {
+                            placement: attrs.get("placement").map(ToString::to_string),
+                            graphics: format!(r"\includegraphics{options}{{{dest}}}"),
+                            label: attrs.get("id").map(ToString::to_string),
+                            caption: String::new(),
+                        });
                     }
-                    Container::Heading { level, id, .. } if *level < 6 => {
+                    Container::Heading { level, id, .. } => {
+                        let mut classes = std::mem::take(&mut self.section_classes);
+                        if let Some(class) = attrs.get("class") {
+                            classes = format!("{classes} {class}");
+                        }
+                        let depth = self.top_level_division.depth(*level, &classes);
+
                         out.write_str(r"\hypertarget{")?;
                         write_escaped(&mut out, id)?;
                         out.write_str("}{%\n")?;
-                        match level {
-                            1 => out.write_str(r"\section")?,
-                            2 => out.write_str(r"\subsection")?,
-                            3 => out.write_str(r"\subsubsection")?,
-                            4 => out.write_str(r"\paragraph")?,
-                            5 => out.write_str(r"\subparagraph")?,
-                            _ => {}
-                        }
-                        if !self.number_sections {
-                            out.write_char('*')?;
+                        match SECTIONING.get((depth + 1) as usize) {
+                            Some(command) => {
+                                write!(out, "\\{command}")?;
+                                if !self.number_sections || has_class(&classes, "unnumbered") {
+                                    out.write_char('*')?;
+                                }
+                            }
+                            // Deeper headings than LaTeX has commands for
+                            None => out.write_str(r"\noindent\textbf")?,
                         }
                         out.write_char('{')?;
                     }
@@ -159,7 +350,12 @@ impl<'s> Writer<'s> {
                     }
                     Container::Math { display } => {
                         self.emit = Emit::Raw;
+                        self.equation = *display && attrs.get("id").is_some();
                         match display {
+                            true if self.equation => {
+                                let id = attrs.get("id").unwrap();
+                                writeln!(out, r"\begin{{equation}}\label{{{id}}}")?
+                            }
                             true => out.write_str(r"\[")?,
                             false => out.write_str(r"\(")?,
                         }
@@ -214,17 +410,22 @@ impl<'s> Writer<'s> {
                 }
             }
             Event::End(c) => match c {
+                // The guard pops the div, which is ignored below unless it is an admonition
+                Container::Div { .. } if self.divs.pop() == Some(true) => {
+                    writeln!(out, r"\end{{djocadmonition}}")?
+                }
                 Container::Section { .. }
                 | Container::ListItem
                 | Container::DescriptionDetails
                 | Container::Div { .. } => {}
                 Container::Paragraph => out.write_str("\n")?,
-                Container::Heading { level, id, .. } if *level < 6 => {
+                Container::Heading { id, .. } => {
                     write!(out, r"}}\label{{{id}}}}}")?;
                     out.write_char('\n')?
                 }
                 Container::Blockquote => writeln!(out, r"\end{{quote}}")?,
-                Container::Image(_, _) => out.write_str("}\n\\end{figure}\n")?,
+                // Figures end in `render_figure_event`
+                Container::Image(_, _) => self.emit = Emit::Escaped,
                 Container::DescriptionList => writeln!(out, r"\end{{description}}")?,
                 Container::DescriptionTerm => writeln!(out, r"]")?,
                 Container::List { kind, .. } => {
@@ -252,9 +453,11 @@ impl<'s> Writer<'s> {
                 Container::Math { display } => {
                     self.emit = Emit::Escaped;
                     match display {
+                        true if self.equation => out.write_str("\n\\end{equation}")?,
                         true => out.write_str(r"\]")?,
                         false => out.write_str(r"\)")?,
                     }
+                    self.equation = false;
                 }
                 Container::Verbatim => out.write_char('}')?,
                 Container::CodeBlock { .. } => {
@@ -268,6 +471,81 @@ impl<'s> Writer<'s> {
         self.first_line = false;
         Ok(())
     }
+
+    fn render_figure_event<W>(&mut self, e: &Event, out: W) -> fmt::Result
+    where
+        W: fmt::Write,
+    {
+        let Some(mut figure) = self.figure.take() else {
+            return Ok(());
+        };
+
+        if let Event::End(Container::Image(..)) = e {
+            return figure.write(out);
+        }
+        self.render_event(e, &mut figure.caption)?;
+        self.figure = Some(figure);
+        Ok(())
+    }
+
+    fn render_table_event<W>(&mut self, e: &Event, out: W) -> fmt::Result
+    where
+        W: fmt::Write,
+    {
+        let Some(mut table) = self.table.take() else {
+            return Ok(());
+        };
+
+        match e {
+            Event::Start(Container::Caption, _) => table.in_caption = true,
+            Event::End(Container::Caption) => table.in_caption = false,
+            Event::Start(Container::TableRow { head }, _) => {
+                // Head rows after the body are written as ordinary rows
+                table.in_head = *head && table.body.is_empty();
+                table.cell = 0;
+            }
+            Event::End(Container::TableRow { .. }) => table.buf().push_str(" \\\\\n"),
+            Event::Start(Container::TableCell { alignment, .. }, _) => {
+                if table.cell > 0 {
+                    table.buf().push_str(" & ");
+                }
+                if table.columns.len() <= table.cell {
+                    table.columns.push(*alignment);
+                    table.widths.push(0);
+                }
+                table.cell_start = table.buf().len();
+            }
+            Event::End(Container::TableCell { .. }) => {
+                let len = table.buf().len() - table.cell_start;
+                let width = &mut table.widths[table.cell];
+                *width = (*width).max(len);
+                table.cell += 1;
+            }
+            Event::End(Container::Table) => return table.write(out),
+            e => {
+                let mut buf = std::mem::take(table.buf());
+                self.render_event(e, &mut buf)?;
+                *table.buf() = buf;
+            }
+        }
+
+        self.table = Some(table);
+        Ok(())
+    }
+}
+
+/// Escapes the characters of `s` that are special in LaTeX.
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    // Writing to a string cannot fail
+    let _ = write_escaped(&mut escaped, s);
+    escaped
+}
+
+/// Escapes the characters of the URL `s` that are special in the argument of
+/// `\href`.
+pub(crate) fn escape_url(s: &str) -> String {
+    s.replace('#', r"\#").replace('%', r"\%")
 }
 
 fn write_escaped<W: fmt::Write>(mut w: W, mut s: &str) -> fmt::Result {
@@ -286,7 +564,7 @@ fn write_escaped<W: fmt::Write>(mut w: W, mut s: &str) -> fmt::Result {
             '\\' => Some(r"\textasciibackslash"),
             _ => None,
         }
-        .map_or(false, |esc| {
+        .is_some_and(|esc| {
             escape = esc;
             true
         })
@@ -297,3 +575,113 @@ fn write_escaped<W: fmt::Write>(mut w: W, mut s: &str) -> fmt::Result {
     }
     w.write_str(s)
 }
+
+#[cfg(test)]
+mod tests {
+    use jotdown::Parser;
+
+    use super::*;
+    use crate::xref::Normalize;
+
+    fn render(src: &str) -> String {
+        let mut s = String::new();
+        Renderer::default().push(Parser::new(src), &mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_table() {
+        let latex = render(concat!(
+            "{#tbl:t}\n",
+            "| a | b | c |\n",
+            "|:--|:-:|--:|\n",
+            "| 1 | _2_ | 3 & 4 |\n",
+            "^ A caption\n",
+        ));
+        assert_eq!(
+            latex,
+            concat!(
+                "\n",
+                "\\begin{longtable}[]{@{}lcr@{}}\n",
+                "\\caption{A caption}\\label{tbl:t}\\tabularnewline\n",
+                "\\toprule\n",
+                "a & b & c \\\\\n",
+                "\\midrule\n",
+                "\\endfirsthead\n",
+                "\\toprule\n",
+                "a & b & c \\\\\n",
+                "\\midrule\n",
+                "\\endhead\n",
+                "\\bottomrule\n",
+                "\\endlastfoot\n",
+                "1 & \\textit{2} & 3 \\& 4 \\\\\n",
+                "\\end{longtable}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_wide_table() {
+        let cell = "word ".repeat(20);
+        let latex = render(&format!("| {cell} | short |\n"));
+        assert!(latex.contains(r"{@{}>{\raggedright\arraybackslash}p{0.88\linewidth}"));
+    }
+
+    #[test]
+    fn test_admonitions() {
+        let latex = render("{title=\"Mind #1\"}\n::: warning\nText.\n:::\n\n::: other\nMore.\n:::");
+        assert_eq!(
+            latex,
+            concat!(
+                "\n",
+                "\\begin{djocadmonition}{orange}{Mind \\#1}\n",
+                "\n",
+                "Text.\n",
+                "\\end{djocadmonition}\n",
+                "\n",
+                "\n",
+                "More.\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_images() {
+        // Paragraphs with a single image are unwrapped by the cross-references
+        let mut s = String::new();
+        let events = Normalize::new(Parser::new(concat!(
+            "![A plot](a.png){#fig:a width=\"50%\" placement=h}\n\n",
+            "![](b.png)\n\n",
+            "An ![icon](i.png){height=12}.",
+        )));
+        Renderer::default().push(events, &mut s).unwrap();
+        assert_eq!(
+            s,
+            concat!(
+                "\\begin{figure}[h]\n\\centering\n",
+                "\\includegraphics[width=0.5\\textwidth]{a.png}",
+                "\\caption{A plot}\\label{fig:a}\n\\end{figure}\n",
+                "\\begin{figure}\n\\centering\n",
+                "\\includegraphics[width=\\textwidth]{b.png}\n\\end{figure}\n",
+                "\nAn \\includegraphics[height=9bp]{i.png}.\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_headings() {
+        let mut s = String::new();
+        Renderer::default()
+            .number_sections(true)
+            .top_level_division(TopLevelDivision::Chapter)
+            .push(
+                Parser::new("{.part}\n# A\n\n# B\n\n{.unnumbered}\n## C\n\n###### D"),
+                &mut s,
+            )
+            .unwrap();
+        assert!(s.contains("\\hypertarget{A}{%\n\\part{A}\\label{A}}"));
+        assert!(s.contains("\\hypertarget{B}{%\n\\chapter{B}\\label{B}}"));
+        assert!(s.contains("\\hypertarget{C}{%\n\\section*{C}\\label{C}}"));
+        assert!(s.contains("\\hypertarget{D}{%\n\\subparagraph{D}\\label{D}}"));
+    }
+}
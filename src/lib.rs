@@ -20,7 +20,7 @@
 //!
 //! let builder = Builder::default();
 //! let mut bytes = Vec::new();
-//! builder.write_latex(&document, &mut bytes).unwrap();
+//! builder.write_latex(&document.parse(), &mut bytes).unwrap();
 //!
 //! let latex = String::from_utf8(bytes).unwrap();
 //!
@@ -30,8 +30,21 @@
 //! assert!(latex.contains("This is some text"));
 //! ```
 
+#[cfg(any(feature = "html", feature = "html-wasm", feature = "latex"))]
+pub(crate) mod admonition;
+pub(crate) mod asset;
+pub(crate) mod figure;
+pub(crate) mod footnote;
+pub(crate) mod front_matter;
+pub(crate) mod highlight;
+pub(crate) mod quotes;
+pub(crate) mod symbols;
+#[cfg(any(feature = "html", feature = "html-wasm", feature = "latex"))]
+pub(crate) mod template;
 pub(crate) mod walk;
+pub(crate) mod xref;
 
+pub mod bib;
 pub mod manifest;
 
 #[cfg(any(feature = "html", feature = "html-wasm"))]
@@ -45,12 +58,14 @@ mod author;
 mod builder;
 mod date;
 mod document;
+mod parsed;
 mod utils;
 
 pub use author::Author;
-pub use builder::Builder;
+pub use builder::{AssetMode, Builder, Resource};
 pub use date::Date;
-pub use document::{Document, DocumentType};
+pub use document::{Document, DocumentType, TopLevelDivision};
 #[doc(inline)]
 pub use manifest::Manifest;
-pub(crate) use utils::kebab;
+pub use parsed::ParsedDocument;
+pub(crate) use utils::{has_class, kebab};
@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use serde::Deserialize;
 
-#[derive(Clone, Deserialize)]
+use crate::{AssetMode, Resource, TopLevelDivision};
+
+#[derive(Clone, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct BuilderManifest {
     #[serde(default, alias = "output")]
@@ -11,6 +13,26 @@ pub struct BuilderManifest {
     pub build_dir: Option<PathBuf>,
     pub locale: Option<String>,
     pub add_title: Option<bool>,
+    pub csl: Option<String>,
+    pub csl_dir: Option<PathBuf>,
+    pub offline: Option<bool>,
+    pub biblatex: Option<bool>,
+    pub biblatex_style: Option<String>,
+    pub highlight_theme: Option<String>,
+    pub top_level_division: Option<TopLevelDivision>,
+    #[serde(default)]
+    pub symbols: HashMap<String, String>,
+    pub emoji_font: Option<String>,
+    pub latex_template: Option<PathBuf>,
+    pub asset_dir: Option<PathBuf>,
+    pub toc: Option<bool>,
+    pub toc_depth: Option<u16>,
+    pub lof: Option<bool>,
+    pub lot: Option<bool>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub hidden: Option<bool>,
+    pub max_nesting: Option<usize>,
 }
 
 impl BuilderManifest {
@@ -21,6 +43,31 @@ impl BuilderManifest {
             build_dir: other.build_dir.or_else(|| self.build_dir.clone()),
             locale: other.locale.or_else(|| self.locale.clone()),
             add_title: other.add_title.or(self.add_title),
+            csl: other.csl.or_else(|| self.csl.clone()),
+            csl_dir: other.csl_dir.or_else(|| self.csl_dir.clone()),
+            offline: other.offline.or(self.offline),
+            biblatex: other.biblatex.or(self.biblatex),
+            biblatex_style: other.biblatex_style.or_else(|| self.biblatex_style.clone()),
+            highlight_theme: other
+                .highlight_theme
+                .or_else(|| self.highlight_theme.clone()),
+            top_level_division: other.top_level_division.or(self.top_level_division),
+            symbols: self
+                .symbols
+                .clone()
+                .into_iter()
+                .chain(other.symbols)
+                .collect(),
+            emoji_font: other.emoji_font.or_else(|| self.emoji_font.clone()),
+            latex_template: other.latex_template.or_else(|| self.latex_template.clone()),
+            asset_dir: other.asset_dir.or_else(|| self.asset_dir.clone()),
+            toc: other.toc.or(self.toc),
+            toc_depth: other.toc_depth.or(self.toc_depth),
+            lof: other.lof.or(self.lof),
+            lot: other.lot.or(self.lot),
+            exclude: [self.exclude.clone(), other.exclude].concat(),
+            hidden: other.hidden.or(self.hidden),
+            max_nesting: other.max_nesting.or(self.max_nesting),
         }
     }
 }
@@ -29,6 +76,12 @@ impl BuilderManifest {
 pub struct Output {
     pub name: Option<String>,
     pub format: OutputFormat,
+    pub assets: Option<AssetMode>,
+    pub srcset: Option<bool>,
+    pub template: Option<PathBuf>,
+    pub stylesheets: Vec<Resource>,
+    pub scripts: Vec<Resource>,
+    pub default_css: Option<bool>,
 }
 
 #[derive(Clone, Deserialize)]
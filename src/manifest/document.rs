@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
 use serde::Deserialize;
 use toml::value::Datetime;
@@ -15,8 +15,20 @@ pub struct DocumentManifest {
     pub authors: Vec<Author>,
     #[serde(default, alias = "text")]
     pub texts: Vec<PathBuf>,
+    #[serde(default, alias = "bibliographies")]
+    pub bibliography: Vec<PathBuf>,
     #[serde(default, alias = "type")]
     pub document_type: DocumentType,
+    pub subtitle: Option<String>,
+    #[serde(rename = "abstract")]
+    pub abstract_: Option<String>,
+    #[serde(default, alias = "keyword")]
+    pub keywords: Vec<String>,
+    pub thanks: Option<String>,
+    pub doi: Option<String>,
+    pub license: Option<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
     #[serde(flatten)]
     pub(crate) builder: BuilderManifest,
 }
@@ -53,21 +53,44 @@ impl Manifest {
             .into_par_iter()
             .try_for_each(|manifest| -> Result<(), ExecutionError> {
                 let builder_manifest = builder_manifest.merge(manifest.builder.to_owned());
-                let builder = Builder::from_manifest(&builder_manifest);
-
-                let document = Document::from_manifest(&manifest)?;
+                let document = Document::from_manifest(&manifest, &builder_manifest)?;
+                // The manifest takes precedence over the front matter
+                let builder =
+                    Builder::from_manifest(&document.options.merge(builder_manifest.clone()));
+                // Parsed once for all outputs
+                let parsed = document.parse();
 
                 for output in builder_manifest.outputs {
                     let path = Path::new(&output.name.unwrap_or(document.filename()))
                         .with_extension(output.format.as_ref());
-                    let file = File::create(path)?;
+                    let file = File::create(&path)?;
+                    let mut builder = builder.clone();
+                    if let Some(dir) = path.parent() {
+                        builder.output_dir(dir);
+                    }
+                    builder.asset_mode(output.assets.unwrap_or_default());
+                    builder.srcset(output.srcset.unwrap_or(false));
+                    if let Some(template) = output.template {
+                        match output.format {
+                            #[cfg(any(feature = "html", feature = "html-wasm"))]
+                            OutputFormat::Html => builder.html_template(template),
+                            _ => builder.latex_template(template),
+                        };
+                    }
+                    output.stylesheets.into_iter().for_each(|stylesheet| {
+                        builder.stylesheet(stylesheet);
+                    });
+                    output.scripts.into_iter().for_each(|script| {
+                        builder.script(script);
+                    });
+                    builder.default_css(output.default_css.unwrap_or(true));
                     match output.format {
                         #[cfg(feature = "pdf")]
-                        OutputFormat::Pdf => builder.write_pdf(&document, file)?,
+                        OutputFormat::Pdf => builder.write_pdf(&parsed, file)?,
                         #[cfg(feature = "latex")]
-                        OutputFormat::Latex => builder.write_latex(&document, file)?,
+                        OutputFormat::Latex => builder.write_latex(&parsed, file)?,
                         #[cfg(any(feature = "html", feature = "html-wasm"))]
-                        OutputFormat::Html => builder.write_html(&document, file)?,
+                        OutputFormat::Html => builder.write_html(&parsed, file)?,
                         OutputFormat::Unknown(format) => {
                             return Err(ExecutionError::UnknownFormat(format))
                         }
@@ -1,4 +1,4 @@
-use std::{fmt, io, str::FromStr};
+use std::{fmt, io, path::PathBuf, str::FromStr};
 
 use serde::{
     de::{self, MapAccess, Visitor},
@@ -6,17 +6,13 @@ use serde::{
 };
 
 use super::{Output, OutputFormat};
-use crate::Author;
+use crate::{AssetMode, Author, Resource};
 
 impl FromStr for Author {
     type Err = io::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self {
-            name: s.into(),
-            email: None,
-            affiliation: None,
-        })
+        Ok(Self::from(s))
     }
 }
 
@@ -30,6 +26,9 @@ impl<'de> Deserialize<'de> for Author {
             pub name: String,
             pub email: Option<String>,
             pub affiliation: Option<String>,
+            pub orcid: Option<String>,
+            #[serde(default)]
+            pub corresponding: bool,
         }
         struct AuthorDefVisitor;
 
@@ -44,11 +43,7 @@ impl<'de> Deserialize<'de> for Author {
             where
                 E: de::Error,
             {
-                Ok(Author {
-                    name: value.into(),
-                    email: None,
-                    affiliation: None,
-                })
+                Ok(Author::from(value))
             }
 
             fn visit_map<M>(self, map: M) -> Result<Self::Value, M::Error>
@@ -61,6 +56,8 @@ impl<'de> Deserialize<'de> for Author {
                     name: aux.name,
                     email: aux.email,
                     affiliation: aux.affiliation,
+                    orcid: aux.orcid,
+                    corresponding: aux.corresponding,
                 })
             }
         }
@@ -75,9 +72,18 @@ impl<'de> Deserialize<'de> for Output {
         D: Deserializer<'de>,
     {
         #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
         struct Aux {
             name: Option<String>,
             format: OutputFormat,
+            assets: Option<AssetMode>,
+            srcset: Option<bool>,
+            template: Option<PathBuf>,
+            #[serde(default, alias = "stylesheet")]
+            stylesheets: Vec<Resource>,
+            #[serde(default, alias = "script")]
+            scripts: Vec<Resource>,
+            default_css: Option<bool>,
         }
         struct OutputVisitor;
 
@@ -95,6 +101,12 @@ impl<'de> Deserialize<'de> for Output {
                 Ok(Output {
                     name: None,
                     format: Deserialize::deserialize(de::value::StrDeserializer::new(value))?,
+                    assets: None,
+                    srcset: None,
+                    template: None,
+                    stylesheets: Vec::new(),
+                    scripts: Vec::new(),
+                    default_css: None,
                 })
             }
 
@@ -107,6 +119,12 @@ impl<'de> Deserialize<'de> for Output {
                 Ok(Output {
                     name: aux.name,
                     format: aux.format,
+                    assets: aux.assets,
+                    srcset: aux.srcset,
+                    template: aux.template,
+                    stylesheets: aux.stylesheets,
+                    scripts: aux.scripts,
+                    default_css: aux.default_css,
                 })
             }
         }
@@ -114,3 +132,47 @@ impl<'de> Deserialize<'de> for Output {
         deserializer.deserialize_any(OutputVisitor)
     }
 }
+
+impl<'de> Deserialize<'de> for Resource {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        enum Aux {
+            Link(String),
+            Inline(PathBuf),
+        }
+        struct ResourceVisitor;
+
+        impl<'de> Visitor<'de> for ResourceVisitor {
+            type Value = Resource;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("string or map")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Resource::Link(value.to_string()))
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Self::Value, M::Error>
+            where
+                M: MapAccess<'de>,
+            {
+                let aux: Aux =
+                    Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(match aux {
+                    Aux::Link(href) => Resource::Link(href),
+                    Aux::Inline(path) => Resource::Inline(path),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(ResourceVisitor)
+    }
+}
@@ -0,0 +1,146 @@
+//! Crate-local module for documents that have been parsed.
+//!
+//! A [`ParsedDocument`] holds the events of each text, with the link
+//! definitions and the ids of headings already resolved by the parser. The
+//! analysis of the document, i.e. its bibliography, footnotes and numbering,
+//! depends on some of the build options and is made the first time a backend
+//! asks for it with those options. Outputs built with the same options share
+//! it, so a document is only parsed and analysed once however many formats it
+//! is written in.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use jotdown::{Event, Parser};
+use rayon::prelude::*;
+
+use crate::{
+    bib::{BibError, Bibliography},
+    footnote::Footnotes,
+    xref::{Backend, CrossRefs},
+    Builder, Document, TopLevelDivision,
+};
+
+/// A document whose texts have been parsed, which can be written in several
+/// formats without parsing it again. It is created with [`Document::parse`].
+pub struct ParsedDocument<'a> {
+    document: &'a Document,
+    /// The events of each text.
+    events: Vec<Vec<Event<'a>>>,
+    /// The analyses made so far, with the options they were made with.
+    analyses: Mutex<Vec<(Options, Arc<Analysis<'a>>)>>,
+}
+
+/// The build options that the analysis of a document depends on.
+#[derive(Clone, PartialEq, Eq)]
+struct Options {
+    number_sections: bool,
+    division: TopLevelDivision,
+    biblatex: bool,
+    csl: Option<String>,
+    csl_dir: Option<PathBuf>,
+    offline: bool,
+    locale: String,
+}
+
+/// The bibliography, footnotes and numbering of a document.
+pub(crate) struct Analysis<'a> {
+    pub(crate) bibliography: Bibliography,
+    pub(crate) footnotes: Footnotes<'a>,
+    pub(crate) crossrefs: CrossRefs,
+}
+
+impl<'a> ParsedDocument<'a> {
+    pub(crate) fn new(document: &'a Document) -> Self {
+        let events = document
+            .texts
+            .par_iter()
+            .map(|text| Parser::new(text).collect())
+            .collect();
+        Self {
+            document,
+            events,
+            analyses: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the document that was parsed.
+    pub fn document(&self) -> &'a Document {
+        self.document
+    }
+
+    /// Returns the events of the text with index `index`.
+    pub(crate) fn events(&self, index: usize) -> impl Iterator<Item = Event<'a>> + '_ {
+        self.events[index].iter().cloned()
+    }
+
+    /// Returns the number of texts.
+    pub(crate) fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns the analysis of the document with the options of `builder`,
+    /// making it if it has not been made with those options before.
+    pub(crate) fn analysis(
+        &self,
+        builder: &Builder,
+        backend: Backend,
+    ) -> Result<Arc<Analysis<'a>>, BibError> {
+        let options = Options {
+            number_sections: builder.number_sections,
+            division: builder.division(self.document),
+            // Only LaTeX output leaves the bibliography to biblatex
+            biblatex: builder.biblatex && backend == Backend::Latex,
+            csl: builder.csl.clone(),
+            csl_dir: builder.csl_dir.clone(),
+            offline: builder.offline,
+            locale: builder.locale.clone(),
+        };
+
+        let mut analyses = self.analyses.lock().unwrap();
+        if let Some((_, analysis)) = analyses.iter().find(|(o, _)| *o == options) {
+            return Ok(analysis.clone());
+        }
+
+        let bibliography = match options.biblatex {
+            true => Bibliography::biblatex(self)?,
+            false => Bibliography::new(
+                self,
+                builder.csl.as_deref(),
+                builder.style_lookup(),
+                &builder.locale,
+            )?,
+        };
+        let analysis = Arc::new(Analysis {
+            footnotes: Footnotes::new(self, &bibliography),
+            crossrefs: CrossRefs::new(self, options.number_sections, options.division),
+            bibliography,
+        });
+        analyses.push((options, analysis.clone()));
+        Ok(analysis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis() {
+        let document = Document::from("# One\n\nText[^a].\n\n[^a]: Note.");
+        let parsed = document.parse();
+        let mut builder = Builder::default();
+        let html = parsed.analysis(&builder, Backend::Html).unwrap();
+        let latex = parsed.analysis(&builder, Backend::Latex).unwrap();
+        assert!(Arc::ptr_eq(&html, &latex));
+        assert_eq!(html.crossrefs.contents[0].number, None);
+
+        builder.number_sections(true);
+        let numbered = parsed.analysis(&builder, Backend::Html).unwrap();
+        assert!(!Arc::ptr_eq(&html, &numbered));
+        assert_eq!(numbered.crossrefs.contents[0].number.as_deref(), Some("1"));
+        assert_eq!(parsed.analyses.lock().unwrap().len(), 2);
+    }
+}
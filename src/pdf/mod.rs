@@ -15,7 +15,7 @@ use std::{
 };
 
 use super::Builder;
-use crate::{latex::LatexError, Document};
+use crate::{asset::Assets, latex::LatexError, ParsedDocument};
 
 impl Builder {
     /// Build the document as PDF and write it to the given writer.
@@ -24,7 +24,8 @@ impl Builder {
     ///
     /// *Example removed because it is not possible to test it on CI.
     /// Works the same as [`Builder::write_latex`].*
-    pub fn write_pdf<W: Write>(&self, document: &Document, mut w: W) -> Result<(), PdfError> {
+    pub fn write_pdf<W: Write>(&self, parsed: &ParsedDocument, mut w: W) -> Result<(), PdfError> {
+        let document = parsed.document();
         let with_name = |e| PdfError::from(e).document_name(&document.title);
         let filename = document.filename();
 
@@ -36,8 +37,61 @@ impl Builder {
 
         let format_cache_path = config.format_cache_path().map_err(with_name)?;
 
+        let mut assets = Assets::new(parsed);
+        if let Some(asset) = assets.missing() {
+            return Err(PdfError {
+                document_name: Some(document.title.clone()),
+                kind: PdfErrorKind::MissingAsset {
+                    path: asset.path.clone(),
+                    source: asset.text.clone(),
+                },
+            });
+        }
+
+        let root = match self.build_dir {
+            Some(ref build_dir) => Some(build_dir.join(&filename)),
+            // The bibliography files and assets still need to be somewhere
+            // on disk for tectonic to find them
+            None => (self.biblatex || !assets.is_empty())
+                .then(|| std::env::temp_dir().join("djoc").join(&filename)),
+        };
+
+        if let Some(ref root) = root {
+            // SVG images are converted before staging, so that the converted
+            // files are staged instead
+            let cache_dir = match self.build_dir {
+                Some(ref build_dir) => build_dir.clone(),
+                None => std::env::temp_dir().join("djoc"),
+            };
+            assets
+                .convert_svgs(&cache_dir)
+                .map_err(|e| e.document_name(&document.title))?;
+
+            fs::create_dir_all(root).map_err(|e| PdfError {
+                document_name: Some(document.title.clone()),
+                kind: PdfErrorKind::CreateDir {
+                    path: root.clone(),
+                    source: e,
+                },
+            })?;
+
+            let mut copy = || -> io::Result<()> {
+                if self.biblatex {
+                    // Tectonic runs bibtex on its own if the document uses a
+                    // bibliography, but the files must be in its root
+                    for file in crate::bib::bib_files(&document.bibliography, &document.walk)? {
+                        if let Some(name) = file.file_name() {
+                            fs::copy(&file, root.join(name))?;
+                        }
+                    }
+                }
+                assets.stage(root)
+            };
+            copy().map_err(|e| PdfError::from(e).document_name(&document.title))?;
+        }
+
         let mut bytes = Vec::new();
-        self.write_latex(document, &mut bytes)?;
+        self.write_latex_with_assets(parsed, &assets, &mut bytes)?;
 
         let files = {
             let mut sb = tectonic::driver::ProcessingSessionBuilder::default();
@@ -52,18 +106,16 @@ impl Builder {
                 .output_format(tectonic::driver::OutputFormat::Pdf)
                 .build_date(SystemTime::now());
 
-            if let Some(ref build_dir) = self.build_dir {
-                let build_dir = build_dir.join(&filename);
-                sb.filesystem_root(&build_dir).output_dir(&build_dir);
-                fs::create_dir_all(&build_dir).map_err(|e| PdfError {
-                    document_name: Some(document.title.clone()),
-                    kind: PdfErrorKind::CreateDir {
-                        path: build_dir,
-                        source: e,
-                    },
-                })?;
-            } else {
-                sb.do_not_write_output_files();
+            match (&root, &self.build_dir) {
+                (Some(root), Some(_)) => {
+                    sb.filesystem_root(root).output_dir(root);
+                }
+                (Some(root), None) => {
+                    sb.filesystem_root(root).do_not_write_output_files();
+                }
+                (None, _) => {
+                    sb.do_not_write_output_files();
+                }
             }
 
             let mut sess = sb.create(&mut status).map_err(with_name)?;
@@ -147,6 +199,13 @@ impl Display for PdfError {
             PdfErrorKind::CreateDir { path, .. } => {
                 write!(f, "failed to create directory {path:?}")
             }
+            PdfErrorKind::MissingAsset { path, source } => {
+                write!(f, "asset {path:?} not found")?;
+                match source {
+                    Some(source) => write!(f, " (referenced in {source:?})"),
+                    None => Ok(()),
+                }
+            }
             PdfErrorKind::NoPdfCreated => write!(f, "engine finished, but no pdf was created"),
         }
     }
@@ -159,7 +218,7 @@ impl Error for PdfError {
             PdfErrorKind::Io(source) => Some(source),
             PdfErrorKind::Latex(source) => Some(source),
             PdfErrorKind::CreateDir { source, .. } => Some(source),
-            PdfErrorKind::NoPdfCreated => None,
+            PdfErrorKind::MissingAsset { .. } | PdfErrorKind::NoPdfCreated => None,
         }
     }
 }
@@ -173,6 +232,15 @@ pub enum PdfErrorKind {
     Tectonic(tectonic::Error),
     Io(io::Error),
     Latex(LatexError),
-    CreateDir { path: PathBuf, source: io::Error },
+    CreateDir {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// An image or other asset referenced in a text does not exist. The
+    /// source is the file of the text, if it was loaded from one.
+    MissingAsset {
+        path: PathBuf,
+        source: Option<PathBuf>,
+    },
     NoPdfCreated,
 }
@@ -0,0 +1,207 @@
+//! Crate-local module for quotation marks.
+//!
+//! Smart quotes are paired within each leaf block and written in the style of
+//! the locale, e.g. „…“ in German or «…» in French and Norwegian. In LaTeX
+//! output, pairs are written as csquotes' `\enquote`, which follows the
+//! language set with polyglossia and alternates the outer and inner marks when
+//! nested. A right single quote without a left one is an apostrophe, and other
+//! unpaired quotes are written as they are.
+
+use std::collections::HashMap;
+
+use jotdown::{Attributes, Container, Event};
+
+use crate::{figure::is_leaf, xref::Backend};
+
+/// The quotation marks of a language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Quotes {
+    double: (&'static str, &'static str),
+    single: (&'static str, &'static str),
+}
+
+impl Quotes {
+    /// Returns the quotation marks of a locale, like `de_DE` or `nb-NO`.
+    /// Unknown languages get English quotes.
+    pub(crate) fn new(locale: &str) -> Self {
+        let (lang, region) = match locale.split_once(['_', '-']) {
+            Some((lang, region)) => (lang, Some(region)),
+            None => (locale, None),
+        };
+        let (double, single) = match (lang, region) {
+            ("de", Some("CH")) => (("«", "»"), ("‹", "›")),
+            ("zh", Some("TW" | "HK")) | ("ja", _) => (("「", "」"), ("『", "』")),
+            ("de" | "cs" | "sk" | "sl" | "bg" | "et" | "is" | "lt", _) => (("„", "“"), ("‚", "‘")),
+            ("fr", _) => (("«\u{202f}", "\u{202f}»"), ("“", "”")),
+            ("nb" | "nn" | "no", _) => (("«", "»"), ("‘", "’")),
+            ("da", _) => (("»", "«"), ("›", "‹")),
+            ("sv" | "fi", _) => (("”", "”"), ("’", "’")),
+            ("pl" | "ro" | "hu", _) => (("„", "”"), ("«", "»")),
+            ("ru" | "uk" | "be", _) => (("«", "»"), ("„", "“")),
+            ("es" | "it" | "pt" | "ca" | "el", _) => (("«", "»"), ("“", "”")),
+            _ => (("“", "”"), ("‘", "’")),
+        };
+        Self { double, single }
+    }
+
+    /// Returns an iterator adapter that replaces the quotes in `events`.
+    pub(crate) fn replace<'s>(
+        self,
+        events: impl Iterator<Item = Event<'s>>,
+        backend: Backend,
+    ) -> impl Iterator<Item = Event<'s>> {
+        // The leaf block being read, which is buffered up to its end since a
+        // left quote is only paired if a right quote follows
+        let mut block = Vec::new();
+        let mut depth = 0;
+        events.flat_map(move |event| {
+            match event {
+                Event::Start(ref c, _) if is_leaf(c) => depth += 1,
+                Event::End(ref c) if is_leaf(c) => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 && block.is_empty() {
+                return vec![event];
+            }
+            block.push(event);
+            if depth > 0 {
+                return Vec::new();
+            }
+            self.write(std::mem::take(&mut block), backend)
+        })
+    }
+
+    fn write<'s>(self, events: Vec<Event<'s>>, backend: Backend) -> Vec<Event<'s>> {
+        let mut paired = HashMap::new();
+        for (i, j) in pairs(&events) {
+            let (marks, star) = match events[i] {
+                Event::LeftDoubleQuote => (self.double, ""),
+                _ => (self.single, "*"),
+            };
+            let (left, right) = match backend {
+                Backend::Html => (
+                    vec![Event::Str(marks.0.into())],
+                    vec![Event::Str(marks.1.into())],
+                ),
+                Backend::Latex => (
+                    raw_latex(format!(r"\enquote{star}{{")),
+                    raw_latex("}".into()),
+                ),
+            };
+            paired.insert(i, left);
+            paired.insert(j, right);
+        }
+        events
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, event)| {
+                if let Some(events) = paired.remove(&i) {
+                    return events;
+                }
+                // Unpaired quotes are only localized in HTML output
+                let mark = match (backend, &event) {
+                    (Backend::Html, Event::LeftDoubleQuote) => self.double.0,
+                    (Backend::Html, Event::RightDoubleQuote) => self.double.1,
+                    (Backend::Html, Event::LeftSingleQuote) => self.single.0,
+                    _ => return vec![event],
+                };
+                vec![Event::Str(mark.into())]
+            })
+            .collect()
+    }
+}
+
+fn raw_latex<'s>(s: String) -> Vec<Event<'s>> {
+    let raw = Container::RawInline { format: "latex" };
+    vec![
+        Event::Start(raw.clone(), Attributes::new()),
+        Event::Str(s.into()),
+        Event::End(raw),
+    ]
+}
+
+/// Returns the indices of the left and right quotes that are paired. Quotes
+/// are not paired across the boundaries of nested blocks, like footnotes.
+fn pairs(events: &[Event]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    // The unpaired left quotes, and whether they are double quotes
+    let mut open: Vec<(usize, bool)> = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::LeftDoubleQuote => open.push((i, true)),
+            Event::LeftSingleQuote => open.push((i, false)),
+            Event::RightDoubleQuote | Event::RightSingleQuote => {
+                let double = matches!(event, Event::RightDoubleQuote);
+                if let Some(k) = open.iter().rposition(|(_, d)| *d == double) {
+                    pairs.push((open[k].0, i));
+                    open.truncate(k);
+                }
+            }
+            Event::Start(c, _) | Event::End(c) if c.is_block() => open.clear(),
+            _ => {}
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use jotdown::{html, Parser, Render};
+
+    use super::*;
+
+    fn render(src: &str, locale: &str, backend: Backend) -> String {
+        let events = Quotes::new(locale).replace(Parser::new(src), backend);
+        let mut s = String::new();
+        match backend {
+            Backend::Html => html::Renderer::default().push(events, &mut s).unwrap(),
+            #[cfg(feature = "latex")]
+            Backend::Latex => crate::latex::Renderer::default()
+                .push(events, &mut s)
+                .unwrap(),
+            #[cfg(not(feature = "latex"))]
+            Backend::Latex => unreachable!(),
+        }
+        s
+    }
+
+    #[test]
+    fn test_html() {
+        let src = r#"He said "it's 'fine'"."#;
+        assert_eq!(
+            render(src, "de_DE", Backend::Html),
+            "<p>He said „it&rsquo;s ‚fine‘“.</p>\n"
+        );
+        assert_eq!(
+            render(src, "nb_NO", Backend::Html),
+            "<p>He said «it&rsquo;s ‘fine’».</p>\n"
+        );
+        assert_eq!(
+            render(r#"A "quote"#, "fr-FR", Backend::Html),
+            "<p>A «\u{202f}quote</p>\n"
+        );
+    }
+
+    #[cfg(feature = "latex")]
+    #[test]
+    fn test_latex() {
+        assert_eq!(
+            render(r#""It's 'fine'", "yes"#, "de_DE", Backend::Latex),
+            "\n\\enquote{It's \\enquote*{fine}}, ``yes\n"
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        // Quotes are not paired across footnotes
+        let events = [
+            Event::Start(Container::Paragraph, Attributes::new()),
+            Event::LeftDoubleQuote,
+            Event::Start(Container::Footnote { label: "a" }, Attributes::new()),
+            Event::RightDoubleQuote,
+            Event::End(Container::Footnote { label: "a" }),
+            Event::End(Container::Paragraph),
+        ];
+        assert!(pairs(&events).is_empty());
+    }
+}
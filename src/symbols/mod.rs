@@ -0,0 +1,162 @@
+//! Crate-local module for resolving symbols (`:name:`).
+//!
+//! A symbol is looked up in the user-defined symbols, then in a small table of
+//! typographic glyphs (e.g. `:tm:` for ™) and finally in a table of emoji,
+//! which contains the [gemoji] aliases used by GitHub (e.g. `:tada:`) and the
+//! Unicode names of emoji in snake case (e.g. `:party_popper:`). Unknown
+//! symbols are left as is, with a warning.
+//!
+//! In LaTeX output, emoji are written in a separate font, as the main font has
+//! no glyphs for them.
+//!
+//! [gemoji]: https://github.com/github/gemoji
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use jotdown::{Attributes, Container, Event};
+use log::warn;
+
+use crate::xref::Backend;
+#[cfg(feature = "latex")]
+use crate::ParsedDocument;
+
+/// The command that switches to the emoji font in LaTeX output.
+pub(crate) const EMOJI_FONT_COMMAND: &str = r"\djocemoji";
+
+/// Glyphs that are available in text fonts, and take precedence over the
+/// emoji with the same names.
+const GLYPHS: &[(&str, &str)] = &[
+    ("tm", "™"),
+    ("copyright", "©"),
+    ("registered", "®"),
+    ("degree", "°"),
+    ("plusmn", "±"),
+    ("times", "×"),
+    ("divide", "÷"),
+    ("sect", "§"),
+    ("para", "¶"),
+    ("dagger", "†"),
+    ("ddagger", "‡"),
+    ("bullet", "•"),
+    ("middot", "·"),
+    ("euro", "€"),
+    ("pound", "£"),
+    ("yen", "¥"),
+    ("cent", "¢"),
+    ("micro", "µ"),
+    ("permil", "‰"),
+];
+
+/// Tab-separated emoji names and emoji.
+const EMOJI: &str = include_str!("emoji.txt");
+
+fn emoji(name: &str) -> Option<&'static str> {
+    static EMOJI_MAP: OnceLock<HashMap<&str, &str>> = OnceLock::new();
+    EMOJI_MAP
+        .get_or_init(|| EMOJI.lines().filter_map(|l| l.split_once('\t')).collect())
+        .get(name)
+        .copied()
+}
+
+/// A resolved symbol.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Symbol<'a> {
+    Text(&'a str),
+    Emoji(&'static str),
+}
+
+pub(crate) struct Symbols<'a> {
+    custom: &'a HashMap<String, String>,
+}
+
+impl<'a> Symbols<'a> {
+    pub(crate) fn new(custom: &'a HashMap<String, String>) -> Self {
+        Self { custom }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Symbol<'a>> {
+        if let Some(value) = self.custom.get(name) {
+            return Some(Symbol::Text(value));
+        }
+        if let Some((_, glyph)) = GLYPHS.iter().find(|(n, _)| *n == name) {
+            return Some(Symbol::Text(glyph));
+        }
+        emoji(name).map(Symbol::Emoji)
+    }
+
+    /// Returns whether any of the symbols in the document is an emoji.
+    #[cfg(feature = "latex")]
+    pub(crate) fn has_emoji(&self, parsed: &ParsedDocument) -> bool {
+        (0..parsed.len()).any(|i| {
+            parsed.events(i).any(|event| {
+                matches!(event, Event::Symbol(name) if matches!(self.get(&name), Some(Symbol::Emoji(_))))
+            })
+        })
+    }
+
+    /// Returns an iterator adapter that replaces the symbols in `events`.
+    pub(crate) fn replace<'b, 's, I>(
+        &'b self,
+        events: I,
+        backend: Backend,
+    ) -> impl Iterator<Item = Event<'s>> + 'b
+    where
+        I: Iterator<Item = Event<'s>> + 'b,
+        's: 'b,
+    {
+        events.flat_map(move |event| {
+            let Event::Symbol(name) = event else {
+                return vec![event];
+            };
+            match self.get(&name) {
+                Some(Symbol::Text(s)) => vec![Event::Str(s.to_string().into())],
+                Some(Symbol::Emoji(s)) if backend == Backend::Latex => {
+                    let raw = Container::RawInline { format: "latex" };
+                    // The font has no variation selectors
+                    let s = s.replace('\u{fe0f}', "");
+                    vec![
+                        Event::Start(raw.clone(), Attributes::new()),
+                        Event::Str(format!("{{{EMOJI_FONT_COMMAND} {s}}}").into()),
+                        Event::End(raw),
+                    ]
+                }
+                Some(Symbol::Emoji(s)) => vec![Event::Str(s.into())],
+                None => {
+                    warn!("Unknown symbol `:{name}:`");
+                    vec![Event::Str(format!(":{name}:").into())]
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jotdown::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let custom = HashMap::from([("version".to_string(), "2.3.1".to_string())]);
+        let symbols = Symbols::new(&custom);
+        assert_eq!(symbols.get("version"), Some(Symbol::Text("2.3.1")));
+        assert_eq!(symbols.get("tm"), Some(Symbol::Text("™")));
+        assert_eq!(symbols.get("tada"), Some(Symbol::Emoji("🎉")));
+        assert_eq!(symbols.get("party_popper"), Some(Symbol::Emoji("🎉")));
+        assert_eq!(symbols.get("warning"), Some(Symbol::Emoji("⚠\u{fe0f}")));
+        assert_eq!(symbols.get("nonexistent"), None);
+    }
+
+    #[test]
+    fn test_replace() {
+        let custom = HashMap::new();
+        let symbols = Symbols::new(&custom);
+        let events: Vec<_> = symbols
+            .replace(Parser::new(":tm: :rocket: :what:"), Backend::Latex)
+            .collect();
+        assert!(events.contains(&Event::Str("™".into())));
+        assert!(events.contains(&Event::Str(r"{\djocemoji 🚀}".into())));
+        assert!(events.contains(&Event::Str(":what:".into())));
+    }
+}
@@ -0,0 +1,201 @@
+//! Crate-local module for filling in output templates.
+//!
+//! A template is the text of an output file with placeholders in the style of
+//! Pandoc. `$name$` is replaced by the value of the variable `name`, and
+//! `$if(name)$ … $else$ … $endif$` keeps the first part if the variable is set
+//! and not empty, and the second part otherwise. The `$else$` part is
+//! optional, and conditionals can be nested. `$$` is a literal dollar sign. A
+//! conditional that is alone on its line leaves no empty line behind.
+
+use std::collections::HashMap;
+
+use log::warn;
+
+/// The variables of a template, keyed by their name.
+pub(crate) type Variables = HashMap<String, String>;
+
+enum Node<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+    If(&'a str, Vec<Node<'a>>, Vec<Node<'a>>),
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+    If(&'a str),
+    Else,
+    EndIf,
+}
+
+/// Fills in `template` with `variables`. Variables that are not defined are
+/// left empty, with a warning.
+///
+/// # Errors
+///
+/// Returns a message describing the problem if the template is malformed.
+pub(crate) fn render(template: &str, variables: &Variables) -> Result<String, String> {
+    let mut tokens = tokenize(template)?.into_iter();
+    let (nodes, _) = parse(&mut tokens, None)?;
+    let mut out = String::new();
+    write(&nodes, variables, &mut out);
+    Ok(out)
+}
+
+/// Turns output written to `bytes` into the value of a variable, leaving out
+/// its final newline, which the template puts after the placeholder.
+pub(crate) fn value(bytes: Vec<u8>) -> String {
+    let s = String::from_utf8_lossy(&bytes);
+    s.strip_suffix('\n').unwrap_or(&s).to_string()
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    // Whether `rest` starts at the start of a line
+    let mut line_start = true;
+    while let Some(start) = rest.find('$') {
+        let (mut text, after) = (&rest[..start], &rest[start + 1..]);
+        if let Some(after) = after.strip_prefix('$') {
+            tokens.push(Token::Text(&rest[..=start]));
+            rest = after;
+            line_start = false;
+            continue;
+        }
+        let end = after
+            .find('$')
+            .ok_or_else(|| format!("unclosed placeholder `${}`", line(after)))?;
+        let placeholder = &after[..end];
+        let mut next = &after[end + 1..];
+        let token = match placeholder {
+            "else" => Token::Else,
+            "endif" => Token::EndIf,
+            _ => match placeholder
+                .strip_prefix("if(")
+                .and_then(|s| s.strip_suffix(')'))
+            {
+                Some(name) if is_name(name) => Token::If(name),
+                None if is_name(placeholder) => Token::Variable(placeholder),
+                _ => return Err(format!("invalid placeholder `${placeholder}$`")),
+            },
+        };
+
+        let indent_start = text.rfind('\n').map(|i| i + 1);
+        let alone = (indent_start.is_some() || line_start)
+            && text[indent_start.unwrap_or(0)..].trim().is_empty()
+            && line(next).trim().is_empty();
+        line_start = false;
+        if alone && !matches!(token, Token::Variable(_)) {
+            // A directive alone on its line is removed along with the line
+            text = &text[..indent_start.unwrap_or(0)];
+            if let Some(i) = next.find('\n') {
+                next = &next[i + 1..];
+                line_start = true;
+            } else {
+                next = "";
+            }
+        }
+        tokens.push(Token::Text(text));
+        tokens.push(token);
+        rest = next;
+    }
+    tokens.push(Token::Text(rest));
+    Ok(tokens)
+}
+
+/// Parses the tokens up to the end of the conditional on `open`, if any,
+/// returning the nodes before and after its `$else$`.
+fn parse<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+    open: Option<&str>,
+) -> Result<(Vec<Node<'a>>, Vec<Node<'a>>), String> {
+    let mut nodes = Vec::new();
+    // The nodes before the `$else$`, once it has been read
+    let mut then = None;
+    loop {
+        let Some(token) = tokens.next() else {
+            return match open {
+                Some(name) => Err(format!("`$if({name})$` is not closed by `$endif$`")),
+                None => Ok((nodes, Vec::new())),
+            };
+        };
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Variable(name) => nodes.push(Node::Variable(name)),
+            Token::If(name) => {
+                let (then, otherwise) = parse(tokens, Some(name))?;
+                nodes.push(Node::If(name, then, otherwise));
+            }
+            Token::Else if open.is_some() && then.is_none() => {
+                then = Some(std::mem::take(&mut nodes));
+            }
+            Token::Else => return Err("unexpected `$else$`".into()),
+            Token::EndIf if open.is_some() => {
+                return Ok(match then {
+                    Some(then) => (then, nodes),
+                    None => (nodes, Vec::new()),
+                });
+            }
+            Token::EndIf => return Err("`$endif$` without `$if$`".into()),
+        }
+    }
+}
+
+fn write(nodes: &[Node], variables: &Variables, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Variable(name) => match variables.get(*name) {
+                Some(value) => out.push_str(value),
+                None => warn!("Unknown template variable `{name}`"),
+            },
+            Node::If(name, then, otherwise) => {
+                match variables.get(*name).is_some_and(|v| !v.is_empty()) {
+                    true => write(then, variables, out),
+                    false => write(otherwise, variables, out),
+                }
+            }
+        }
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Returns the start of `s` up to the end of its line.
+fn line(s: &str) -> &str {
+    s.split('\n').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let variables: Variables = [("title", "A"), ("empty", ""), ("author", "B")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let render = |template| render(template, &variables);
+
+        assert_eq!(render("# $title$ costs $$5").unwrap(), "# A costs $5");
+        assert_eq!(
+            render("$if(author)$by $author$$else$anonymous$endif$.").unwrap(),
+            "by B."
+        );
+        assert_eq!(
+            render("a\n$if(empty)$\nb\n$else$\n  c\n$if(title)$\nd\n$endif$\n$endif$\ne").unwrap(),
+            "a\n  c\nd\ne"
+        );
+        assert_eq!(render("$unknown$.").unwrap(), ".");
+
+        assert!(render("$if(title)$").is_err());
+        assert!(render("$endif$").is_err());
+        assert!(render("$title").is_err());
+        assert!(render("$no spaces$").is_err());
+    }
+}
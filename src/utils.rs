@@ -23,6 +23,19 @@ pub fn kebab(s: &str) -> String {
         .collect()
 }
 
+/// Returns whether the space-separated list of classes contains `class`.
+pub fn has_class(classes: &str, class: &str) -> bool {
+    classes.split_whitespace().any(|c| c == class)
+}
+
+/// Escapes the characters that have a special meaning in HTML.
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,203 @@
+//! Crate-local module for the patterns that leave paths out of a walk.
+//!
+//! Patterns follow the syntax of `.gitignore` files. `*` matches anything but
+//! a slash, `?` a single character other than a slash, `[a-z]` a character
+//! class and `**` any number of directories. A pattern with a slash at the
+//! start or in the middle is relative to the directory it is given for, while
+//! other patterns match at any depth. A trailing slash only matches
+//! directories, and a leading `!` includes paths again that an earlier pattern
+//! left out.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use regex::Regex;
+
+/// The files in a directory with patterns for paths to leave out.
+const IGNORE_FILES: &[&str] = &[".gitignore", ".djocignore"];
+
+struct Pattern {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+impl Pattern {
+    /// Parses a line of an ignore file, returning `None` for blank lines and
+    /// comments.
+    fn new(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, glob) = match line.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, line),
+        };
+        let (dir_only, glob) = match glob.strip_suffix('/') {
+            Some(glob) => (true, glob),
+            None => (false, glob),
+        };
+        let anchored = glob.contains('/');
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+        match Regex::new(&format!("{prefix}{}$", regex(glob))) {
+            Ok(regex) => Some(Self {
+                regex,
+                negated,
+                dir_only,
+                anchored,
+            }),
+            Err(_) => {
+                warn!("Invalid pattern `{line}`");
+                None
+            }
+        }
+    }
+}
+
+/// Translates a glob to a regular expression.
+fn regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.clone().take_while(|&c| c != ']').collect();
+                let len = class.chars().count();
+                if len == chars.clone().count() {
+                    // An unclosed bracket is taken literally
+                    regex.push_str(r"\[");
+                    continue;
+                }
+                chars.nth(len);
+                let class = match class.strip_prefix('!') {
+                    Some(class) => format!("^{class}"),
+                    None => class,
+                };
+                regex.push('[');
+                regex.push_str(&class.replace('\\', r"\\").replace('[', r"\["));
+                regex.push(']');
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    regex.push_str(&regex::escape(&c.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
+
+/// The patterns given for a directory.
+pub(crate) struct Ignore {
+    dir: PathBuf,
+    patterns: Vec<Pattern>,
+    /// Whether patterns without a slash also match paths outside the
+    /// directory.
+    global: bool,
+}
+
+impl Ignore {
+    /// Creates a set of patterns that are relative to `dir`.
+    pub(crate) fn new<'a>(dir: PathBuf, lines: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            dir,
+            patterns: lines.into_iter().filter_map(Pattern::new).collect(),
+            global: false,
+        }
+    }
+
+    /// Creates a set of patterns where those without a slash match anywhere,
+    /// and the others are relative to `dir`.
+    pub(crate) fn global<'a>(dir: PathBuf, lines: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            global: true,
+            ..Self::new(dir, lines)
+        }
+    }
+
+    /// Reads the `.gitignore` and `.djocignore` files in `dir`, if there are
+    /// any.
+    pub(crate) fn from_dir(dir: &Path) -> Option<Self> {
+        let mut lines = String::new();
+        for name in IGNORE_FILES {
+            if let Ok(s) = fs::read_to_string(dir.join(name)) {
+                lines.push_str(&s);
+                lines.push('\n');
+            }
+        }
+        let ignore = Self::new(dir.to_path_buf(), lines.lines());
+        (!ignore.patterns.is_empty()).then_some(ignore)
+    }
+
+    /// Returns whether the last pattern that matches `path` leaves it out, or
+    /// `None` if no pattern matches or the path is not inside the directory.
+    pub(crate) fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let (relative, inside) = match path.strip_prefix(&self.dir) {
+            Ok(relative) => (relative, true),
+            Err(_) if self.global => (path, false),
+            Err(_) => return None,
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        self.patterns
+            .iter()
+            .rev()
+            .filter(|p| inside || !p.anchored)
+            .find(|p| (is_dir || !p.dir_only) && p.regex.is_match(&relative))
+            .map(|p| !p.negated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matched() {
+        let ignore = Ignore::new(
+            PathBuf::from("/book"),
+            [
+                "# Drafts",
+                "*.bak",
+                "/notes.dj",
+                "drafts/",
+                "!drafts/keep.dj",
+                "src/**/old-[!0-9]*.dj",
+            ],
+        );
+        let matched = |path: &str, is_dir| ignore.matched(Path::new(path), is_dir);
+        assert_eq!(matched("/book/a/b.bak", false), Some(true));
+        assert_eq!(matched("/book/notes.dj", false), Some(true));
+        assert_eq!(matched("/book/a/notes.dj", false), None);
+        assert_eq!(matched("/book/a/drafts", true), Some(true));
+        assert_eq!(matched("/book/drafts", false), None);
+        assert_eq!(matched("/book/drafts/keep.dj", false), Some(false));
+        assert_eq!(matched("/book/src/a/b/old-x.dj", false), Some(true));
+        assert_eq!(matched("/book/src/old-1.dj", false), None);
+        assert_eq!(matched("/other/a.bak", false), None);
+
+        let exclude = Ignore::global(PathBuf::from("/book"), ["*.bak", "/notes.dj"]);
+        assert_eq!(
+            exclude.matched(Path::new("/other/a.bak"), false),
+            Some(true)
+        );
+        assert_eq!(exclude.matched(Path::new("/other/notes.dj"), false), None);
+    }
+}
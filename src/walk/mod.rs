@@ -0,0 +1,460 @@
+//! Crate-local module for walking the directory tree.
+//!
+//! The entries of a directory are visited in natural order, where numbers in
+//! file names are compared by their value, so that `2-intro.dj` comes before
+//! `10-results.dj`. A directory can pin the order of its entries with an index
+//! file, either `_order.toml` with a list of paths under `order`, or
+//! `SUMMARY.dj` with a link to each path. Paths are relative to the directory
+//! and may point into subdirectories. The listed entries come first, in the
+//! given order, followed by the others in natural order.
+
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use jotdown::{Container, Event, Parser};
+use log::warn;
+use serde::Deserialize;
+
+use self::ignore::Ignore;
+use crate::manifest::BuilderManifest;
+
+mod ignore;
+
+/// The names of the index files that set the order of a directory, which are
+/// not visited themselves.
+const INDEX_FILES: &[&str] = &["_order.toml", "SUMMARY.dj"];
+
+/// The rules for which paths a walk visits.
+#[derive(Clone, Debug)]
+pub(crate) struct WalkOptions {
+    /// Whether to visit hidden files and directories, whose names start with
+    /// a dot.
+    pub(crate) hidden: bool,
+    /// How many levels of directories to descend into.
+    pub(crate) max_nesting: usize,
+    /// Patterns for paths to leave out, relative to the working directory.
+    pub(crate) exclude: Vec<String>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            hidden: false,
+            max_nesting: 10,
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl WalkOptions {
+    pub(crate) fn from_manifest(manifest: &BuilderManifest) -> Self {
+        let default = Self::default();
+        Self {
+            hidden: manifest.hidden.unwrap_or(default.hidden),
+            max_nesting: manifest.max_nesting.unwrap_or(default.max_nesting),
+            exclude: manifest.exclude.clone(),
+        }
+    }
+}
+
+enum WalkNode {
+    /// A directory and its depth below the root.
+    Dir(PathBuf, usize),
+    File(PathBuf),
+}
+
+/// An iterator that walks a directory recursively.
+///
+/// Hidden entries are skipped unless [`WalkOptions::hidden`] is set, and so
+/// are paths matched by the `.gitignore` and `.djocignore` files of the
+/// directories walked through, including those above the root up to the root
+/// of a Git repository, or by [`WalkOptions::exclude`]. Each directory is only
+/// visited once, which guards against cycles of symbolic links.
+pub struct Walker {
+    stack: Vec<WalkNode>,
+    /// The files that have been visited, since an index file may list a file
+    /// in a subdirectory that is visited again later.
+    visited: HashSet<PathBuf>,
+    /// The canonical paths of the directories that have been visited.
+    visited_dirs: HashSet<PathBuf>,
+    /// The working directory, which relative paths are resolved against
+    /// before they are matched by the ignore patterns.
+    base: PathBuf,
+    ignores: Vec<Ignore>,
+    exclude: Ignore,
+    options: WalkOptions,
+}
+
+impl Walker {
+    /// Creates a new `Walker` from a path, following the given rules. A path
+    /// that points to a file is always visited.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the path does not exist or if the
+    /// process does not have permission to read the path.
+    pub fn new<P: AsRef<Path>>(path: P, options: &WalkOptions) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "path does not exist",
+            ));
+        }
+
+        let base = env::current_dir()?;
+        let mut walker = Self {
+            stack: Vec::new(),
+            visited: HashSet::new(),
+            visited_dirs: HashSet::new(),
+            exclude: Ignore::global(base.clone(), options.exclude.iter().map(String::as_str)),
+            base,
+            ignores: Vec::new(),
+            options: options.clone(),
+        };
+        if path.is_dir() {
+            walker.ignores = ancestor_ignores(&walker.base.join(path));
+            walker.push_entries(path, 1)?;
+        } else {
+            walker.stack.push(WalkNode::File(path.into()));
+        }
+        Ok(walker)
+    }
+
+    /// Returns a new iterator that only yields paths with the given extensions.
+    pub fn filter_extensions(self, extensions: &'static [&'static str]) -> FilteredWalker {
+        FilteredWalker {
+            inner: self,
+            extensions,
+        }
+    }
+
+    /// Pushes the entries of `dir` that are not left out onto the stack, so
+    /// that they are popped in order.
+    fn push_entries(&mut self, dir: &Path, depth: usize) -> io::Result<()> {
+        if !self.visited_dirs.insert(fs::canonicalize(dir)?) {
+            warn!("Skipping {dir:?}, which has already been visited");
+            return Ok(());
+        }
+        if let Some(ignore) = Ignore::from_dir(&self.base.join(dir)) {
+            self.ignores.push(ignore);
+        }
+        let entries = entries(dir)?;
+        let entries: Vec<_> = entries
+            .into_iter()
+            .filter(|path| !self.is_ignored(path))
+            .collect();
+        self.stack.extend(entries.into_iter().rev().map(|path| {
+            if path.is_dir() {
+                WalkNode::Dir(path, depth)
+            } else {
+                WalkNode::File(path)
+            }
+        }));
+        Ok(())
+    }
+
+    /// Returns whether `path` is hidden or matched by an ignore pattern. The
+    /// last matching pattern decides, and the exclude patterns come last.
+    fn is_ignored(&self, path: &Path) -> bool {
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if hidden && !self.options.hidden {
+            return true;
+        }
+        let path = self.base.join(path);
+        let is_dir = path.is_dir();
+        self.ignores
+            .iter()
+            .chain([&self.exclude])
+            .rev()
+            .find_map(|ignore| ignore.matched(&path, is_dir))
+            .unwrap_or_default()
+    }
+}
+
+impl Iterator for Walker {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                WalkNode::Dir(path, depth) => {
+                    if depth < self.options.max_nesting {
+                        if let Err(e) = self.push_entries(&path, depth + 1) {
+                            warn!("Could not read {path:?}: {e}");
+                        }
+                    } else {
+                        warn!("Skipping {path:?}, which is nested too deeply");
+                    }
+                }
+                WalkNode::File(path) => {
+                    let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                    if self.visited.insert(key) {
+                        return Some(path);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An iterator that walks a directory recursively and only yields paths with
+/// the given extensions.
+pub struct FilteredWalker {
+    inner: Walker,
+    extensions: &'static [&'static str],
+}
+
+impl Iterator for FilteredWalker {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.find(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str().map(|ext| self.extensions.contains(&ext)))
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Lists the entries of `dir` in the order set by its index file, if any, and
+/// otherwise in natural order.
+fn entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_none_or(|name| !INDEX_FILES.contains(&name))
+        })
+        .collect();
+    entries.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    let Some(order) = index(dir)? else {
+        return Ok(entries);
+    };
+    let mut ordered = Vec::new();
+    for path in order {
+        let path = dir.join(path);
+        if !path.exists() {
+            warn!("{path:?} is listed in the index of {dir:?} but does not exist");
+        } else if !ordered.contains(&path) {
+            ordered.push(path);
+        }
+    }
+    entries.retain(|path| !ordered.contains(path));
+    ordered.extend(entries);
+    Ok(ordered)
+}
+
+/// Reads the ignore files in the directories above `dir`, up to the root of
+/// the Git repository that it is in, if any.
+fn ancestor_ignores(dir: &Path) -> Vec<Ignore> {
+    let Some(root) = dir.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return Vec::new();
+    };
+    let mut ignores: Vec<_> = dir
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(root))
+        .filter_map(Ignore::from_dir)
+        .collect();
+    // The patterns of the inner directories take precedence
+    ignores.reverse();
+    ignores
+}
+
+#[derive(Deserialize)]
+struct Order {
+    order: Vec<PathBuf>,
+}
+
+/// Reads the paths listed in the index file of `dir`.
+fn index(dir: &Path) -> io::Result<Option<Vec<PathBuf>>> {
+    let path = dir.join("_order.toml");
+    if path.is_file() {
+        let order: Order = toml::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{path:?}: {e}")))?;
+        return Ok(Some(order.order));
+    }
+
+    let path = dir.join("SUMMARY.dj");
+    if path.is_file() {
+        let summary = fs::read_to_string(&path)?;
+        let links = Parser::new(&summary)
+            .filter_map(|event| match event {
+                Event::Start(Container::Link(dest, _), _) if !dest.contains("://") => {
+                    Some(PathBuf::from(dest.as_ref()))
+                }
+                _ => None,
+            })
+            .collect();
+        return Ok(Some(links));
+    }
+    Ok(None)
+}
+
+/// Compares two strings in natural order, where runs of digits are compared
+/// by their value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.char_indices().peekable(), b.char_indices().peekable());
+    while let (Some(&(i, x)), Some(&(j, y))) = (a_chars.peek(), b_chars.peek()) {
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_num = digits(&a[i..]);
+            let b_num = digits(&b[j..]);
+            let (a_trimmed, b_trimmed) =
+                (a_num.trim_start_matches('0'), b_num.trim_start_matches('0'));
+            let ordering = a_trimmed
+                .len()
+                .cmp(&b_trimmed.len())
+                .then_with(|| a_trimmed.cmp(b_trimmed));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            a_chars.nth(a_num.len() - 1);
+            b_chars.nth(b_num.len() - 1);
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            a_chars.next();
+            b_chars.next();
+        }
+    }
+    a_chars
+        .peek()
+        .is_some()
+        .cmp(&b_chars.peek().is_some())
+        .then_with(|| a.cmp(b))
+}
+
+/// Returns the run of ASCII digits at the start of `s`.
+fn digits(s: &str) -> &str {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = [
+            "10-results.dj",
+            "2-intro.dj",
+            "02-methods.dj",
+            "a.dj",
+            "1.dj",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            [
+                "1.dj",
+                "2-intro.dj",
+                "02-methods.dj",
+                "10-results.dj",
+                "a.dj"
+            ]
+        );
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_index() {
+        let dir = std::env::temp_dir().join(format!("djoc-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("part")).unwrap();
+        for file in ["10.dj", "9.dj", "part/1.dj", "part/2.dj"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let names = |dir: &Path| -> Vec<_> {
+            Walker::new(dir, &WalkOptions::default())
+                .unwrap()
+                .filter_extensions(&["dj"])
+                .map(|path| path.strip_prefix(dir).unwrap().to_owned())
+                .collect()
+        };
+        let paths = |names: &[&str]| -> Vec<_> { names.iter().map(PathBuf::from).collect() };
+
+        assert_eq!(
+            names(&dir),
+            paths(&["9.dj", "10.dj", "part/1.dj", "part/2.dj"])
+        );
+
+        fs::write(dir.join("_order.toml"), r#"order = ["part/2.dj", "10.dj"]"#).unwrap();
+        assert_eq!(
+            names(&dir),
+            paths(&["part/2.dj", "10.dj", "9.dj", "part/1.dj"])
+        );
+
+        fs::remove_file(dir.join("_order.toml")).unwrap();
+        fs::write(
+            dir.join("part/SUMMARY.dj"),
+            "- [Two](2.dj)\n- [One](1.dj)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&dir),
+            paths(&["9.dj", "10.dj", "part/2.dj", "part/1.dj"])
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ignore() {
+        let dir = std::env::temp_dir().join(format!("djoc-ignore-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("drafts")).unwrap();
+        for file in [
+            "1.dj",
+            ".hidden.dj",
+            ".git/x.dj",
+            "drafts/2.dj",
+            "skip-3.dj",
+            "a/4.dj",
+            "a/b/5.dj",
+            "a/b/c/6.dj",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "drafts/\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("a/loop")).unwrap();
+
+        let names = |options: &WalkOptions| -> Vec<_> {
+            Walker::new(&dir, options)
+                .unwrap()
+                .filter_extensions(&["dj"])
+                .map(|path| path.strip_prefix(&dir).unwrap().to_owned())
+                .collect()
+        };
+        let paths = |names: &[&str]| -> Vec<_> { names.iter().map(PathBuf::from).collect() };
+
+        assert_eq!(
+            names(&WalkOptions::default()),
+            paths(&["1.dj", "a/4.dj", "a/b/5.dj", "a/b/c/6.dj", "skip-3.dj"])
+        );
+        let options = WalkOptions {
+            hidden: true,
+            max_nesting: 2,
+            exclude: vec!["skip-*.dj".into(), ".git/".into()],
+        };
+        assert_eq!(names(&options), paths(&[".hidden.dj", "1.dj", "a/4.dj"]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,819 @@
+//! Crate-local module for numbering and resolving cross-references.
+//!
+//! Sections, figures, tables and labelled display equations are numbered
+//! across all texts of a document. They can be referenced either with an
+//! empty link to their id (`[](#fig:plot)`) or, if the id has one of the
+//! prefixes `sec:`, `fig:`, `tbl:` or `eq:`, like a citation (`[@fig:plot]`.)
+//! Both are replaced by e.g. "Figure 3" or "Eq. (4)".
+//!
+//! A paragraph that consists of a single image is unwrapped, making the image a
+//! figure, and its attributes are moved to the image. Figures are numbered
+//! unless their caption, the alt text, is empty. An id on a paragraph that
+//! starts with a display equation is treated as the id of that equation.
+//!
+//! The headings, numbered figures and tables are also collected in order, for
+//! the table of contents and the lists of figures and tables in HTML output.
+//! Figures and tables without an id get one there, like `figure-3`.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    iter::Peekable,
+};
+
+use jotdown::{Attributes, Container, Event, LinkType, SpanLinkType};
+use log::warn;
+
+use crate::{figure::is_leaf, has_class, ParsedDocument, TopLevelDivision};
+
+/// Prefixes of ids that can be referenced with the citation syntax.
+const LABEL_PREFIXES: &[&str] = &["sec:", "fig:", "tbl:", "eq:"];
+
+/// Returns whether `key` is a cross-reference label rather than a citation
+/// key.
+pub(crate) fn is_label(key: &str) -> bool {
+    LABEL_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
+}
+
+/// The output format references are resolved for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Backend {
+    /// Numbers are written out, and inserted into headings, table captions
+    /// and equations.
+    Html,
+    /// Numbers are left to LaTeX through `\label` and `\ref`.
+    Latex,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Part,
+    Chapter,
+    Section,
+    Figure,
+    Table,
+    Equation,
+}
+
+impl Kind {
+    fn is_heading(self) -> bool {
+        matches!(self, Kind::Part | Kind::Chapter | Kind::Section)
+    }
+}
+
+/// An entry of the table of contents or of a list of figures or tables, which
+/// only HTML output lists itself.
+#[cfg_attr(not(any(feature = "html", feature = "html-wasm")), allow(dead_code))]
+pub(crate) struct Entry {
+    /// The level of a heading, or 0 for figures and tables.
+    pub(crate) level: u16,
+    pub(crate) id: String,
+    pub(crate) number: Option<String>,
+    /// The plain text title of a heading, or the caption of a figure or table.
+    pub(crate) title: String,
+}
+
+/// Returns the id of a figure or table without one in HTML output.
+fn generated_id(kind: Kind, number: &str) -> String {
+    match kind {
+        Kind::Table => format!("table-{number}"),
+        _ => format!("figure-{number}"),
+    }
+}
+
+struct Target {
+    kind: Kind,
+    /// The number of the target, or `None` for unnumbered sections.
+    number: Option<String>,
+    /// The plain text title of a section.
+    title: String,
+}
+
+/// Keeps track of the numbers of sections, figures, tables and equations.
+#[derive(Clone, Default)]
+struct Counter {
+    number_sections: bool,
+    division: TopLevelDivision,
+    /// The classes of the current section, which apply to its heading.
+    classes: String,
+    /// Whether the last heading has the class `unnumbered`.
+    unnumbered: bool,
+    parts: usize,
+    /// The counters of chapters, sections and so on down to subparagraphs.
+    sections: [usize; 6],
+    /// The number of leaf blocks the current event is in, in which images
+    /// are inline rather than figures.
+    leaf: usize,
+    figures: usize,
+    tables: usize,
+    equations: usize,
+    table_id: Option<String>,
+}
+
+impl Counter {
+    /// Advances the counter, returning the kind, id and number of the element
+    /// that starts with `event`, if it is numbered. `next` is the event after
+    /// it.
+    fn count(
+        &mut self,
+        event: &Event,
+        next: Option<&Event>,
+    ) -> Option<(Kind, Option<String>, Option<String>)> {
+        match event {
+            Event::Start(c, _) if is_leaf(c) => self.leaf += 1,
+            Event::End(c) if is_leaf(c) => self.leaf -= 1,
+            _ => {}
+        }
+
+        match event {
+            Event::Start(Container::Section { .. }, attrs) => {
+                self.classes = attrs
+                    .get("class")
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                None
+            }
+            Event::Start(Container::Heading { level, id, .. }, attrs) => {
+                let mut classes = std::mem::take(&mut self.classes);
+                if let Some(class) = attrs.get("class") {
+                    classes = format!("{classes} {class}");
+                }
+                let depth = self.division.depth(*level, &classes);
+                let kind = match depth {
+                    -1 => Kind::Part,
+                    0 => Kind::Chapter,
+                    _ => Kind::Section,
+                };
+                let id = Some(id.to_string());
+                self.unnumbered = has_class(&classes, "unnumbered");
+                if !self.number_sections || self.unnumbered || depth > 5 {
+                    return Some((kind, id, None));
+                }
+                if depth < 0 {
+                    self.parts += 1;
+                    return Some((kind, id, Some(roman(self.parts))));
+                }
+
+                let depth = depth as usize;
+                self.sections[depth] += 1;
+                self.sections[depth + 1..].fill(0);
+                // Articles have no chapters to prefix section numbers with
+                let top = match self.division {
+                    TopLevelDivision::Section => depth.min(1),
+                    _ => 0,
+                };
+                let number = self.sections[top..=depth]
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".");
+                Some((kind, id, Some(number)))
+            }
+            // Figures without a caption are not numbered
+            Event::Start(Container::Image(..), attrs)
+                if self.leaf == 0 && !matches!(next, Some(Event::End(Container::Image(..)))) =>
+            {
+                self.figures += 1;
+                let id = attrs.get("id").map(ToString::to_string);
+                Some((Kind::Figure, id, Some(self.figures.to_string())))
+            }
+            Event::Start(Container::Table, attrs) => {
+                self.table_id = attrs.get("id").map(ToString::to_string);
+                None
+            }
+            Event::Start(Container::Caption, _) => {
+                self.tables += 1;
+                let id = self.table_id.take();
+                Some((Kind::Table, id, Some(self.tables.to_string())))
+            }
+            Event::Start(Container::Math { display: true }, attrs) => {
+                let id = attrs.get("id")?.to_string();
+                self.equations += 1;
+                Some((Kind::Equation, Some(id), Some(self.equations.to_string())))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Formats a part number as an upper case roman numeral, like LaTeX does.
+fn roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut s = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            s.push_str(numeral);
+            n -= value;
+        }
+    }
+    s
+}
+
+/// The numbered elements of a document and the ids that can be linked to.
+pub(crate) struct CrossRefs {
+    targets: HashMap<String, Target>,
+    anchors: HashSet<String>,
+    /// The state of the counter at the start of each text.
+    starts: Vec<Counter>,
+    /// The headings, except for unnumbered ones.
+    #[cfg_attr(not(any(feature = "html", feature = "html-wasm")), allow(dead_code))]
+    pub(crate) contents: Vec<Entry>,
+    #[cfg_attr(not(any(feature = "html", feature = "html-wasm")), allow(dead_code))]
+    pub(crate) figures: Vec<Entry>,
+    #[cfg_attr(not(any(feature = "html", feature = "html-wasm")), allow(dead_code))]
+    pub(crate) tables: Vec<Entry>,
+}
+
+impl CrossRefs {
+    pub fn new(parsed: &ParsedDocument, number_sections: bool, division: TopLevelDivision) -> Self {
+        let mut targets: HashMap<String, Target> = HashMap::new();
+        let mut anchors = HashSet::new();
+        let mut starts = Vec::new();
+        let mut contents: Vec<Entry> = Vec::new();
+        let mut figures = Vec::new();
+        let mut tables = Vec::new();
+
+        let mut counter = Counter {
+            number_sections,
+            division,
+            ..Counter::default()
+        };
+        for i in 0..parsed.len() {
+            starts.push(counter.clone());
+
+            // The title or caption being read, with the id of its target and
+            // the index of its entry
+            let mut title: Option<(Kind, Option<String>, Option<usize>, String)> = None;
+            let mut level = 0;
+            let mut events = Normalize::new(parsed.events(i));
+            while let Some(event) = events.next() {
+                if let Event::Start(Container::Heading { level: l, .. }, _) = event {
+                    level = l;
+                }
+                if let Event::Start(c, attrs) = &event {
+                    if let Container::Section { id } = c {
+                        anchors.insert(id.to_string());
+                    }
+                    if let Some(id) = attrs.get("id") {
+                        anchors.insert(id.to_string());
+                    }
+                }
+
+                match &event {
+                    Event::Str(s) => {
+                        if let Some((.., ref mut title)) = title {
+                            title.push_str(s);
+                        }
+                    }
+                    Event::End(c) => {
+                        let end = match title {
+                            Some((kind, ..)) if kind.is_heading() => {
+                                matches!(c, Container::Heading { .. })
+                            }
+                            Some((Kind::Figure, ..)) => matches!(c, Container::Image(..)),
+                            Some((Kind::Table, ..)) => matches!(c, Container::Caption),
+                            _ => false,
+                        };
+                        if end {
+                            let (kind, id, entry, title) = title.take().unwrap();
+                            let entries = match kind {
+                                Kind::Figure => &mut figures,
+                                Kind::Table => &mut tables,
+                                _ => &mut contents,
+                            };
+                            if let Some(i) = entry {
+                                entries[i].title = title.clone();
+                            }
+                            if let Some(target) = id.and_then(|id| targets.get_mut(&id)) {
+                                target.title = title;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                if let Some((kind, id, number)) = counter.count(&event, events.peek()) {
+                    let entry = match kind {
+                        Kind::Figure | Kind::Table => {
+                            // Always numbered
+                            let number = number.clone().unwrap_or_default();
+                            Some(Entry {
+                                level: 0,
+                                id: id.clone().unwrap_or_else(|| generated_id(kind, &number)),
+                                number: Some(number),
+                                title: String::new(),
+                            })
+                        }
+                        _ if kind.is_heading() && !counter.unnumbered => Some(Entry {
+                            level,
+                            id: id.clone().unwrap_or_default(),
+                            number: number.clone(),
+                            title: String::new(),
+                        }),
+                        _ => None,
+                    };
+                    let entries = match kind {
+                        Kind::Figure => &mut figures,
+                        Kind::Table => &mut tables,
+                        _ => &mut contents,
+                    };
+                    let index = entry.map(|entry| {
+                        entries.push(entry);
+                        entries.len() - 1
+                    });
+                    if kind != Kind::Equation {
+                        title = Some((kind, id.clone(), index, String::new()));
+                    }
+
+                    let Some(id) = id else { continue };
+                    anchors.insert(id.clone());
+                    targets.insert(
+                        id,
+                        Target {
+                            kind,
+                            number,
+                            title: String::new(),
+                        },
+                    );
+                }
+            }
+        }
+
+        Self {
+            targets,
+            anchors,
+            starts,
+            contents,
+            figures,
+            tables,
+        }
+    }
+
+    /// Resolves the references in the events of the text with index `index`.
+    pub fn resolve<'a, 's, I>(
+        &'a self,
+        index: usize,
+        events: I,
+        backend: Backend,
+    ) -> Resolve<'a, 's, I>
+    where
+        I: Iterator<Item = Event<'s>>,
+    {
+        Resolve {
+            refs: self,
+            inner: Normalize::new(events),
+            backend,
+            counter: self.starts.get(index).cloned().unwrap_or_default(),
+            tag: None,
+            verbatim: 0,
+            buf: VecDeque::new(),
+        }
+    }
+
+    /// Returns the events that a reference to `id` is replaced with.
+    fn reference<'s>(&self, id: &str, backend: Backend) -> Option<Vec<Event<'s>>> {
+        let target = self.targets.get(id)?;
+        let name = match target.kind {
+            Kind::Part => "Part",
+            Kind::Chapter => "Chapter",
+            Kind::Section => "Section",
+            Kind::Figure => "Figure",
+            Kind::Table => "Table",
+            Kind::Equation => "Eq.",
+        };
+
+        let Some(ref number) = target.number else {
+            return Some(vec![Event::Str(target.title.clone().into())]);
+        };
+
+        Some(match backend {
+            Backend::Html => {
+                let number = match target.kind {
+                    Kind::Equation => format!("({number})"),
+                    _ => number.clone(),
+                };
+                vec![Event::Str(format!("{name} {number}").into())]
+            }
+            Backend::Latex => {
+                let number = match target.kind {
+                    Kind::Equation => format!(r"(\ref*{{{id}}})"),
+                    _ => format!(r"\ref*{{{id}}}"),
+                };
+                let raw = Container::RawInline { format: "latex" };
+                vec![
+                    Event::Start(raw.clone(), Attributes::new()),
+                    Event::Str(format!("{name}~{number}").into()),
+                    Event::End(raw),
+                ]
+            }
+        })
+    }
+
+    /// Returns the events for a link to `id`, or `None` if `id` is unknown.
+    fn link<'s>(&self, id: &str, backend: Backend) -> Option<Vec<Event<'s>>> {
+        let link = Container::Link(
+            format!("#{id}").into(),
+            LinkType::Span(SpanLinkType::Inline),
+        );
+        let mut events = vec![Event::Start(link.clone(), Attributes::new())];
+        events.extend(self.reference(id, backend)?);
+        events.push(Event::End(link));
+        Some(events)
+    }
+}
+
+/// Iterator adapter that resolves the references in a text.
+pub(crate) struct Resolve<'a, 's, I: Iterator<Item = Event<'s>>> {
+    refs: &'a CrossRefs,
+    inner: Normalize<'s, I>,
+    backend: Backend,
+    counter: Counter,
+    /// The number of the equation currently being read, in HTML output.
+    tag: Option<String>,
+    /// The number of verbatim containers the current event is in, whose text
+    /// is left as is.
+    verbatim: usize,
+    buf: VecDeque<Event<'s>>,
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Resolve<'a, 's, I> {
+    /// Replaces references of the form `[@fig:plot]` in `s`.
+    fn split_str(&mut self, s: &str) {
+        let mut last = 0;
+        let mut rest = s;
+        while let Some(start) = rest.find("[@") {
+            let Some(len) = rest[start..].find(']') else {
+                break;
+            };
+            let label = &rest[start + 2..start + len];
+            let end = start + len + 1;
+            if is_label(label) && !label.contains(char::is_whitespace) {
+                match self.refs.link(label, self.backend) {
+                    Some(events) => {
+                        let offset = s.len() - rest.len();
+                        if offset + start > last {
+                            self.buf
+                                .push_back(Event::Str(s[last..offset + start].to_string().into()));
+                        }
+                        self.buf.extend(events);
+                        last = offset + end;
+                    }
+                    None => warn!("Reference to unknown label `{label}`"),
+                }
+            }
+            rest = &rest[end..];
+        }
+
+        if last < s.len() {
+            self.buf.push_back(Event::Str(s[last..].to_string().into()));
+        }
+    }
+}
+
+impl<'a, 's, I: Iterator<Item = Event<'s>>> Iterator for Resolve<'a, 's, I> {
+    type Item = Event<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.buf.pop_front() {
+            return Some(event);
+        }
+
+        let mut event = self.inner.next()?;
+        let html = self.backend == Backend::Html;
+        match event {
+            Event::Start(ref c, _) if is_verbatim(c) => self.verbatim += 1,
+            Event::End(ref c) if is_verbatim(c) => {
+                self.verbatim = self.verbatim.saturating_sub(1);
+            }
+            _ => {}
+        }
+
+        // Give parts and chapters their semantics in HTML, as they are all
+        // sections there
+        if let (true, Event::Start(Container::Section { .. }, attrs)) = (html, &mut event) {
+            if let Some(Event::Start(Container::Heading { level, .. }, _)) = self.inner.peek() {
+                let classes = attrs
+                    .get("class")
+                    .map(ToString::to_string)
+                    .unwrap_or_default();
+                match self.counter.division.depth(*level, &classes) {
+                    -1 => attrs.insert("role", "doc-part".into()),
+                    0 => attrs.insert("role", "doc-chapter".into()),
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some((kind, id, Some(number))) = self.counter.count(&event, self.inner.peek()) {
+            match kind {
+                Kind::Part | Kind::Chapter | Kind::Section if html => {
+                    let mut attrs = Attributes::new();
+                    attrs.insert("class", "section-number".into());
+                    self.buf.extend([
+                        Event::Start(Container::Span, attrs),
+                        Event::Str(number.into()),
+                        Event::End(Container::Span),
+                        Event::Str(" ".into()),
+                    ]);
+                }
+                // Give figures and tables an id to link to from the lists
+                Kind::Figure if html && id.is_none() => {
+                    if let Event::Start(_, ref mut attrs) = event {
+                        attrs.insert("id", generated_id(kind, &number).into());
+                    }
+                }
+                Kind::Table if html => {
+                    if id.is_none() {
+                        let raw = Container::RawInline { format: "html" };
+                        let anchor =
+                            format!(r#"<span id="{}"></span>"#, generated_id(kind, &number));
+                        self.buf.extend([
+                            Event::Start(raw.clone(), Attributes::new()),
+                            Event::Str(anchor.into()),
+                            Event::End(raw),
+                        ]);
+                    }
+                    self.buf
+                        .push_back(Event::Str(format!("Table {number}: ").into()))
+                }
+                Kind::Equation if html => self.tag = Some(number),
+                _ => {}
+            }
+            self.buf.push_front(event);
+            return self.buf.pop_front();
+        }
+
+        match event {
+            Event::Str(s) if self.tag.is_some() => {
+                let tag = self.tag.take().unwrap();
+                Some(Event::Str(format!(r"{s}\tag{{{tag}}}").into()))
+            }
+            Event::Str(s) if self.verbatim == 0 && s.contains("[@") => {
+                self.split_str(&s);
+                self.buf.pop_front()
+            }
+            Event::Start(Container::Link(ref dest, _), _) if dest.starts_with('#') => {
+                let id = &dest[1..];
+                let empty = matches!(self.inner.peek(), Some(Event::End(Container::Link(..))));
+                match self.refs.reference(id, self.backend) {
+                    Some(reference) if empty => self.buf.extend(reference),
+                    None if !self.refs.anchors.contains(id) => {
+                        warn!("Reference to unknown id `{id}`");
+                    }
+                    _ => {}
+                }
+                Some(event)
+            }
+            event => Some(event),
+        }
+    }
+}
+
+/// Returns whether the text in `c` is taken literally, so that it contains no
+/// references.
+fn is_verbatim(c: &Container) -> bool {
+    matches!(
+        c,
+        Container::Verbatim
+            | Container::CodeBlock { .. }
+            | Container::Math { .. }
+            | Container::RawBlock { .. }
+            | Container::RawInline { .. }
+    )
+}
+
+/// Iterator adapter that unwraps paragraphs consisting of a single image and
+/// moves the id of a paragraph to the display equation it starts with.
+pub(crate) struct Normalize<'s, I: Iterator<Item = Event<'s>>> {
+    inner: Peekable<I>,
+    buf: VecDeque<Event<'s>>,
+}
+
+impl<'s, I: Iterator<Item = Event<'s>>> Normalize<'s, I> {
+    pub(crate) fn new(inner: I) -> Self {
+        Self {
+            inner: inner.peekable(),
+            buf: VecDeque::new(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Event<'s>> {
+        match self.buf.front() {
+            Some(event) => Some(event),
+            None => self.inner.peek(),
+        }
+    }
+
+    /// Reads a paragraph that starts with an image, unwrapping it if it
+    /// consists of just the image.
+    fn image_paragraph(&mut self, attrs: Attributes<'s>) -> Event<'s> {
+        let mut image = Vec::new();
+        for event in self.inner.by_ref() {
+            let end = matches!(event, Event::End(Container::Image(..)));
+            image.push(event);
+            if end {
+                break;
+            }
+        }
+
+        if !matches!(self.inner.peek(), Some(Event::End(Container::Paragraph))) {
+            self.buf.extend(image);
+            return Event::Start(Container::Paragraph, attrs);
+        }
+        self.inner.next();
+
+        if let Some(Event::Start(Container::Image(..), child)) = image.first_mut() {
+            for (key, value) in attrs.iter() {
+                if key == "class" || child.get(key).is_none() {
+                    child.insert(key, value.clone());
+                }
+            }
+        }
+        self.buf.extend(image);
+        self.buf.pop_front().unwrap()
+    }
+}
+
+impl<'s, I: Iterator<Item = Event<'s>>> Iterator for Normalize<'s, I> {
+    type Item = Event<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.buf.pop_front() {
+            return Some(event);
+        }
+
+        let event = self.inner.next()?;
+        let Event::Start(Container::Paragraph, attrs) = event else {
+            return Some(event);
+        };
+        if let Some(Event::Start(Container::Image(..), _)) = self.inner.peek() {
+            return Some(self.image_paragraph(attrs));
+        }
+        let Some(id) = attrs.get("id").cloned() else {
+            return Some(Event::Start(Container::Paragraph, attrs));
+        };
+
+        match self.inner.peek_mut() {
+            Some(Event::Start(Container::Math { display: true }, child))
+                if child.get("id").is_none() =>
+            {
+                child.insert("id", id);
+                let mut rest = Attributes::new();
+                for (key, value) in attrs.iter().filter(|(key, _)| *key != "id") {
+                    rest.insert(key, value.clone());
+                }
+                Some(Event::Start(Container::Paragraph, rest))
+            }
+            _ => Some(Event::Start(Container::Paragraph, attrs)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jotdown::{html, Render};
+
+    use super::*;
+    use crate::Document;
+
+    fn render(texts: &[&str], number_sections: bool) -> Vec<String> {
+        render_with(texts, number_sections, TopLevelDivision::Section)
+    }
+
+    fn render_with(
+        texts: &[&str],
+        number_sections: bool,
+        division: TopLevelDivision,
+    ) -> Vec<String> {
+        let mut document = Document::default();
+        document.texts(texts.iter().copied());
+        let parsed = document.parse();
+        let refs = CrossRefs::new(&parsed, number_sections, division);
+        (0..texts.len())
+            .map(|i| {
+                let mut s = String::new();
+                let events = refs.resolve(i, parsed.events(i), Backend::Html);
+                html::Renderer::default().push(events, &mut s).unwrap();
+                s
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_numbering() {
+        let html = render(
+            &[
+                "# One\n\n{#sec:sub}\n## Sub\n\n![A](a.png)",
+                concat!(
+                    "# Two\n\n{#fig:b}\n![B](b.png)\n\n$$`x`{#eq:x}\n\n",
+                    "See [@fig:b], [](#sec:sub), [](#eq:x) and [@fig:none].",
+                ),
+            ],
+            true,
+        );
+        assert!(html[0].contains(r#"<span class="section-number">1.1</span> Sub"#));
+        assert!(html[1].contains(r#"<span class="section-number">2</span> Two"#));
+        assert!(html[1].contains(r"x\tag{1}"));
+        assert!(html[1].contains(concat!(
+            r##"See <a href="#fig:b">Figure 2</a>, "##,
+            r##"<a href="#sec:sub">Section 1.1</a>, "##,
+            r##"<a href="#eq:x">Eq. (1)</a> and [@fig:none]."##,
+        )));
+    }
+
+    #[test]
+    fn test_verbatim() {
+        let html = render(
+            &["{#fig:a}\n![A](a.png)\n\nSee [@fig:a], not `[@fig:a]`.\n\n```\n[@fig:a]\n```"],
+            false,
+        );
+        assert!(
+            html[0].contains(r##"See <a href="#fig:a">Figure 1</a>, not <code>[@fig:a]</code>."##)
+        );
+        assert!(html[0].contains("<pre><code>[@fig:a]\n</code></pre>"));
+    }
+
+    #[test]
+    fn test_figures() {
+        let html = render(
+            &["![](a.png)\n\nAn ![icon](i.png).\n\n{#fig:b}\n![B](b.png)\n\nSee [@fig:b]."],
+            false,
+        );
+        assert!(html[0].contains(r#"<img id="fig:b" alt="B" src="b.png">"#));
+        assert!(html[0].contains(r##"See <a href="#fig:b">Figure 1</a>."##));
+    }
+
+    #[test]
+    fn test_outline() {
+        let mut document = Document::default();
+        document.texts([
+            "# One\n\n{.unnumbered}\n## Sub\n\n![A *plot*](a.png)",
+            "## Two\n\n{#tbl:t}\n| a |\n\n^ A table",
+        ]);
+        let refs = CrossRefs::new(&document.parse(), true, TopLevelDivision::Section);
+        let contents: Vec<_> = refs
+            .contents
+            .iter()
+            .map(|e| {
+                (
+                    e.level,
+                    e.id.as_str(),
+                    e.number.as_deref(),
+                    e.title.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            contents,
+            [(1, "One", Some("1"), "One"), (2, "Two", Some("1.1"), "Two")]
+        );
+        assert_eq!(refs.figures[0].id, "figure-1");
+        assert_eq!(refs.figures[0].title, "A plot");
+        assert_eq!(refs.tables[0].id, "tbl:t");
+        assert_eq!(refs.tables[0].title, "A table");
+
+        let html = render(&["![A](a.png)\n\n| a |\n\n^ B"], false);
+        assert!(html[0].contains(r#"<img id="figure-1" alt="A" src="a.png">"#));
+        assert!(html[0].contains(r#"<caption><span id="table-1"></span>Table 1: B</caption>"#));
+    }
+
+    #[test]
+    fn test_unnumbered_sections() {
+        let html = render(
+            &["{#sec:intro}\n# Introduction\n\nSee [](#sec:intro)."],
+            false,
+        );
+        assert!(html[0].contains(r##"See <a href="#sec:intro">Introduction</a>."##));
+    }
+
+    #[test]
+    fn test_chapters() {
+        let html = render_with(
+            &[concat!(
+                "{.part}\n# Basics\n\n# One\n\n## Sub\n\n{.unnumbered}\n# Preface\n\n",
+                "{#sec:two}\n# Two\n\nSee [@sec:two].",
+            )],
+            true,
+            TopLevelDivision::Chapter,
+        );
+        assert!(html[0].contains(r#"<section role="doc-part" id="Basics" class="part">"#));
+        assert!(html[0].contains(r#"<span class="section-number">I</span> Basics"#));
+        assert!(html[0].contains(r#"<section role="doc-chapter" id="One">"#));
+        assert!(html[0].contains(r#"<span class="section-number">1.1</span> Sub"#));
+        assert!(html[0].contains("<h1>Preface</h1>"));
+        assert!(html[0].contains(r##"See <a href="#sec:two">Chapter 2</a>."##));
+    }
+}